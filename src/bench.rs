@@ -0,0 +1,167 @@
+use crate::semaphore::Semaphore;
+use crate::{
+    fetch_nixpkgs_with_nix, generate_package_note_template, package_info_from_meta, save_json,
+    Args, Evaluator,
+};
+use anyhow::{anyhow, Context};
+use colored::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// packages.json to benchmark against
+    #[arg(long, default_value = "fixtures/packages.json")]
+    fixture: String,
+
+    /// Use an existing nixpkgs checkout instead of fetching one
+    #[arg(long)]
+    nixpkgs: Option<String>,
+
+    /// Where to write the JSON report
+    #[arg(long, default_value = "bench.json")]
+    report: String,
+}
+
+#[derive(Serialize)]
+struct BenchReport {
+    fixture: String,
+    threads: usize,
+    packages: usize,
+    succeeded: usize,
+    failed: usize,
+    /// `None` when an existing checkout was passed with `--nixpkgs`
+    fetch_secs: Option<f64>,
+    enumerate_secs: f64,
+    process_secs: f64,
+    /// Successfully evaluated packages only; failures return quickly and
+    /// would inflate it
+    packages_per_sec: f64,
+    /// High-water mark of the resident set size, Linux only
+    peak_memory_kb: Option<u64>,
+}
+
+/// Runs evaluation and note rendering over a fixed `packages.json` without
+/// writing any notes, timing each phase of a regular run.
+pub fn run(bench_args: &BenchArgs, args: &Args, threads: usize) -> anyhow::Result<()> {
+    println!(
+        "{} {}",
+        "⏱️  Benchmarking with fixture:".cyan().bold(),
+        bench_args.fixture.bright_white()
+    );
+
+    let (nixpkgs_path, fetch_secs) = match &bench_args.nixpkgs {
+        Some(path) => (path.clone(), None),
+        None => {
+            let start = Instant::now();
+            let path = fetch_nixpkgs_with_nix(&args.git_url, &args.revision)?;
+            (path, Some(start.elapsed().as_secs_f64()))
+        }
+    };
+
+    let start = Instant::now();
+    let parsed = crate::load_packages_json(&bench_args.fixture)?;
+    let packages = parsed["packages"]
+        .as_object()
+        .ok_or_else(|| anyhow!("fixture {} has no \"packages\" object", bench_args.fixture))?;
+    let enumerate_secs = start.elapsed().as_secs_f64();
+
+    // Evaluated the way a run does: in sorted `--batch-size` chunks, with a
+    // call per package only for what a batch left out, at most
+    // `--max-eval-jobs` at a time. The derivation cache is left out, so
+    // every derivation is evaluated.
+    let mut packages_vec: Vec<_> = packages.iter().collect();
+    packages_vec.sort_unstable_by_key(|(name, _)| *name);
+    let eval_jobs = Semaphore::new(args.max_eval_jobs.unwrap_or(threads));
+    let evaluator = Evaluator {
+        args,
+        nixpkgs_path: &nixpkgs_path,
+        cache: None,
+        eval_jobs: &eval_jobs,
+    };
+    let succeeded = AtomicUsize::new(0);
+    let start = Instant::now();
+    packages_vec
+        .par_chunks(args.batch_size.max(1))
+        .for_each(|chunk| {
+            let mut chunk_evaluation = evaluator.chunk(chunk);
+            for (name, info) in chunk {
+                let mut package_info = package_info_from_meta(name, info);
+                if chunk_evaluation.evaluate(name, &mut package_info).0.is_ok() {
+                    generate_package_note_template(
+                        &package_info,
+                        args.notes_extension(),
+                        args.frontmatter,
+                    );
+                    succeeded.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+    let process_secs = start.elapsed().as_secs_f64();
+
+    let succeeded = succeeded.into_inner();
+    let report = BenchReport {
+        fixture: bench_args.fixture.clone(),
+        threads,
+        packages: packages.len(),
+        succeeded,
+        failed: packages.len() - succeeded,
+        fetch_secs,
+        enumerate_secs,
+        process_secs,
+        packages_per_sec: succeeded as f64 / process_secs.max(f64::EPSILON),
+        peak_memory_kb: peak_memory_kb(),
+    };
+
+    print_report(&report);
+    save_json(&bench_args.report, &report, args.pretty_json(true))
+        .with_context(|| format!("failed to write bench report {}", bench_args.report))?;
+    println!(
+        "{} {}",
+        "✅ Bench report written to:".green().bold(),
+        bench_args.report.bright_white()
+    );
+
+    Ok(())
+}
+
+fn print_report(report: &BenchReport) {
+    let row = |label: &str, value: String| {
+        println!("  {:<18} {}", label.cyan(), value.bright_white());
+    };
+    row(
+        "packages",
+        format!("{} ({} failed)", report.packages, report.failed),
+    );
+    row("threads", report.threads.to_string());
+    row(
+        "fetch",
+        report
+            .fetch_secs
+            .map_or("skipped".to_string(), |secs| format!("{:.3}s", secs)),
+    );
+    row("enumerate", format!("{:.3}s", report.enumerate_secs));
+    row("process", format!("{:.3}s", report.process_secs));
+    row(
+        "throughput",
+        format!("{:.2} evaluated packages/s", report.packages_per_sec),
+    );
+    row(
+        "peak memory",
+        report
+            .peak_memory_kb
+            .map_or("unknown".to_string(), |kb| format!("{} MiB", kb / 1024)),
+    );
+}
+
+/// Reads `VmHWM` from `/proc/self/status`.
+fn peak_memory_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
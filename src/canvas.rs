@@ -0,0 +1,183 @@
+use crate::graph::DependencyGraph;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Size of every card. Obsidian shows a preview of the note inside.
+const WIDTH: i64 = 400;
+const HEIGHT: i64 = 240;
+/// Room between the cards, for the edges.
+const GAP_X: i64 = 200;
+const GAP_Y: i64 = 40;
+
+/// Obsidian's JSON Canvas format, which is what `.canvas` files hold.
+#[derive(Serialize, Default)]
+pub struct Canvas {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    id: String,
+    #[serde(flatten)]
+    content: Content,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Content {
+    /// A note of the vault, by path relative to the vault
+    File { file: String },
+    /// A derivation that has no note, by its label
+    Text { text: String },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Edge {
+    id: String,
+    from_node: String,
+    from_side: &'static str,
+    to_node: String,
+    to_side: &'static str,
+}
+
+impl Canvas {
+    /// Adds a card for `drv_path` with its top left corner at `x`, `y`,
+    /// returning its id.
+    fn add_node(
+        &mut self,
+        graph: &DependencyGraph,
+        notes: &HashMap<String, String>,
+        drv_path: &str,
+        x: i64,
+        y: i64,
+    ) -> String {
+        let id = format!("node-{}", self.nodes.len());
+        let content = match notes.get(drv_path) {
+            Some(note) => Content::File { file: note.clone() },
+            None => Content::Text {
+                text: format!("**{}**", graph.label(drv_path)),
+            },
+        };
+        self.nodes.push(Node {
+            id: id.clone(),
+            content,
+            x,
+            y,
+            width: WIDTH,
+            height: HEIGHT,
+        });
+        id
+    }
+
+    /// Adds an edge from the right side of one card to the left of another.
+    fn add_edge(&mut self, from: &str, to: &str) {
+        self.edges.push(Edge {
+            id: format!("edge-{}", self.edges.len()),
+            from_node: from.to_string(),
+            from_side: "right",
+            to_node: to.to_string(),
+            to_side: "left",
+        });
+    }
+
+    /// Adds a column of cards centred on `y = 0`, returning their ids.
+    fn add_column(
+        &mut self,
+        graph: &DependencyGraph,
+        notes: &HashMap<String, String>,
+        drv_paths: &[&str],
+        x: i64,
+    ) -> Vec<String> {
+        let top = -(drv_paths.len() as i64 * (HEIGHT + GAP_Y) - GAP_Y) / 2;
+        drv_paths
+            .iter()
+            .enumerate()
+            .map(|(row, drv_path)| {
+                self.add_node(
+                    graph,
+                    notes,
+                    drv_path,
+                    x,
+                    top + row as i64 * (HEIGHT + GAP_Y),
+                )
+            })
+            .collect()
+    }
+}
+
+/// `root` in the middle, the processed packages depending on it to its
+/// left and its direct dependencies to its right. `notes` maps drv paths to
+/// their notes; derivations without one get a text card.
+pub fn neighborhood(
+    graph: &DependencyGraph,
+    notes: &HashMap<String, String>,
+    root: &str,
+) -> Canvas {
+    let mut canvas = Canvas::default();
+    let mut dependents = graph.dependents().remove(root).unwrap_or_default();
+    dependents.retain(|dependent| *dependent != root);
+    let mut dependencies: Vec<&str> = graph
+        .direct_dependencies(root)
+        .iter()
+        .map(String::as_str)
+        .filter(|dependency| *dependency != root)
+        .collect();
+    dependencies.sort_by_key(|drv_path| (graph.label(drv_path), *drv_path));
+    dependencies.dedup();
+
+    let root_id = canvas.add_node(graph, notes, root, 0, -HEIGHT / 2);
+    for id in canvas.add_column(graph, notes, &dependents, -(WIDTH + GAP_X)) {
+        canvas.add_edge(&id, &root_id);
+    }
+    for id in canvas.add_column(graph, notes, &dependencies, WIDTH + GAP_X) {
+        canvas.add_edge(&root_id, &id);
+    }
+    canvas
+}
+
+/// Every processed package whose attribute path starts with `set.`, in a
+/// grid sorted by attribute, with the dependencies between them. `None` if
+/// no processed package is in the set.
+pub fn package_set(
+    graph: &DependencyGraph,
+    notes: &HashMap<String, String>,
+    set: &str,
+) -> Option<Canvas> {
+    let members = graph.members(set);
+    if members.is_empty() {
+        return None;
+    }
+    let columns = (members.len() as f64).sqrt().ceil() as i64;
+
+    let mut canvas = Canvas::default();
+    let mut ids = HashMap::new();
+    for (index, drv_path) in members.iter().enumerate() {
+        let index = index as i64;
+        let id = canvas.add_node(
+            graph,
+            notes,
+            drv_path,
+            (index % columns) * (WIDTH + GAP_X),
+            (index / columns) * (HEIGHT + GAP_Y),
+        );
+        ids.insert(*drv_path, id);
+    }
+    let mut seen = HashSet::new();
+    for drv_path in &members {
+        for dependency in graph.direct_dependencies(drv_path) {
+            if let Some(to) = ids.get(dependency.as_str()) {
+                if seen.insert((*drv_path, dependency.as_str())) {
+                    let from = ids[drv_path].clone();
+                    canvas.add_edge(&from, to);
+                }
+            }
+        }
+    }
+    Some(canvas)
+}
@@ -0,0 +1,104 @@
+use crate::{Args, VaultError};
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// Config file read from the working directory when `--config` isn't given.
+pub const DEFAULT_CONFIG: &str = "nixpkgs-vault.toml";
+
+/// Parses the command line like `Args::parse`, then fills in every option
+/// neither it nor a `NIXPKGS_VAULT_*` environment variable sets from the
+/// config file. Keys are the long option names, with
+/// `-` or `_`; switches take `true` or `false` and repeatable options an
+/// array. Exits on invalid arguments and `--help`, as `Args::parse` does.
+pub fn parse_args<I, T>(argv: I) -> Result<Args, VaultError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let argv: Vec<OsString> = argv.into_iter().map(Into::into).collect();
+    let matches = Args::command().get_matches_from(argv.clone());
+    let path = match matches.get_one::<String>("config") {
+        Some(path) => path.clone(),
+        None if Path::new(DEFAULT_CONFIG).is_file() => DEFAULT_CONFIG.to_string(),
+        None => return Args::from_arg_matches(&matches).map_err(|e| e.exit()),
+    };
+
+    let invalid = |message: String| VaultError::InvalidConfig {
+        path: path.clone(),
+        message,
+    };
+    let data = fs::read_to_string(&path)
+        .map_err(|e| VaultError::io(format!("failed to read config {}", path), e))?;
+    let table: toml::Table = data
+        .parse()
+        .map_err(|e: toml::de::Error| invalid(e.message().to_string()))?;
+
+    let command = Args::command();
+    let mut flags = Vec::new();
+    for (key, value) in &table {
+        let id = key.replace('-', "_");
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some())
+            .filter(|_| id != "config")
+        else {
+            return Err(invalid(format!("unknown option `{}`", key)));
+        };
+        // The command line and environment variables win
+        if matches!(
+            matches.value_source(&id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let long = arg.get_long().expect("filtered on having a long name");
+        let switch = matches!(arg.get_action(), ArgAction::SetTrue);
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        // Counted flags like `verbose = 2` are repeated
+        if let (ArgAction::Count, toml::Value::Integer(count)) = (arg.get_action(), value) {
+            flags.extend((0..*count).map(|_| format!("--{}", long)));
+            continue;
+        }
+        for value in values {
+            match (value, switch) {
+                (toml::Value::Boolean(true), true) => flags.push(format!("--{}", long)),
+                (toml::Value::Boolean(false), true) => {}
+                (_, true) => {
+                    return Err(invalid(format!("`{}` must be true or false", key)));
+                }
+                (toml::Value::String(s), false) => flags.push(format!("--{}={}", long, s)),
+                (
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_),
+                    false,
+                ) => flags.push(format!("--{}={}", long, value)),
+                (_, false) => {
+                    return Err(invalid(format!(
+                        "`{}` must be a string, number or boolean",
+                        key
+                    )));
+                }
+            }
+        }
+    }
+
+    // Options from the file go first so subcommands still come last
+    let argv = argv
+        .iter()
+        .take(1)
+        .cloned()
+        .chain(flags.into_iter().map(OsString::from))
+        .chain(argv.iter().skip(1).cloned());
+    Args::try_parse_from(argv).map_err(|e| {
+        // Only the first line, without clap's usage and help hints
+        let message = e.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        invalid(first_line.trim_start_matches("error: ").to_string())
+    })
+}
@@ -0,0 +1,103 @@
+use crate::PackageInfo;
+use redb::{Database, MultimapTableDefinition, TableDefinition};
+use std::fs;
+use std::sync::Mutex;
+
+/// Attribute name -> `PackageInfo` as JSON.
+const PACKAGES: TableDefinition<&str, &str> = TableDefinition::new("packages");
+/// License short name -> attribute names.
+const BY_LICENSE: MultimapTableDefinition<&str, &str> = MultimapTableDefinition::new("by_license");
+/// Maintainer handle -> attribute names.
+const BY_MAINTAINER: MultimapTableDefinition<&str, &str> =
+    MultimapTableDefinition::new("by_maintainer");
+
+/// Packages written per transaction; committing is what costs in redb.
+const BATCH_SIZE: usize = 512;
+
+struct Record {
+    attribute: String,
+    json: String,
+    license: String,
+    maintainers: Vec<String>,
+}
+
+/// Embedded key-value store written with `--db`. Workers queue records and
+/// whichever fills the batch commits it through the single handle.
+pub struct PackageDb {
+    database: Database,
+    pending: Mutex<Vec<Record>>,
+}
+
+impl PackageDb {
+    /// Creates the database, replacing one left by an earlier run so it
+    /// only holds the packages of this run.
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        let database = Database::create(path)?;
+
+        // Create the tables up front so readers find them even if empty
+        let transaction = database.begin_write()?;
+        transaction.open_table(PACKAGES)?;
+        transaction.open_multimap_table(BY_LICENSE)?;
+        transaction.open_multimap_table(BY_MAINTAINER)?;
+        transaction.commit()?;
+
+        Ok(PackageDb {
+            database,
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn insert(&self, package_info: &PackageInfo) -> anyhow::Result<()> {
+        let record = Record {
+            attribute: package_info.attribute.clone(),
+            json: serde_json::to_string(package_info)?,
+            license: package_info.license_short_name.clone(),
+            maintainers: package_info
+                .maintainers
+                .iter()
+                .map(|m| m.handle())
+                .collect(),
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.push(record);
+        if pending.len() >= BATCH_SIZE {
+            let batch = std::mem::take(&mut *pending);
+            // Holding the lock keeps a single writer at a time
+            self.commit(batch)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, batch: Vec<Record>) -> anyhow::Result<()> {
+        let transaction = self.database.begin_write()?;
+        {
+            let mut packages = transaction.open_table(PACKAGES)?;
+            let mut by_license = transaction.open_multimap_table(BY_LICENSE)?;
+            let mut by_maintainer = transaction.open_multimap_table(BY_MAINTAINER)?;
+            for record in &batch {
+                packages.insert(record.attribute.as_str(), record.json.as_str())?;
+                by_license.insert(record.license.as_str(), record.attribute.as_str())?;
+                for maintainer in &record.maintainers {
+                    by_maintainer.insert(maintainer.as_str(), record.attribute.as_str())?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Commits whatever is still queued.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.commit(batch)
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Raw `nix derivation show` output of packages evaluated in earlier runs.
+/// Stored as `.cache/derivations/<nixpkgs>/<attribute>.json`, where
+/// `<nixpkgs>` is the name of the fetched store path. That path is content
+/// addressed, so a branch like `nixos-unstable` that moved since the last
+/// run gets a directory of its own instead of stale derivations. Evaluations
+/// for another `--system` get `<nixpkgs>-<system>`.
+pub struct DerivationCache {
+    dir: PathBuf,
+}
+
+/// Directory holding the caches of every nixpkgs, removed by `--clear-cache`.
+pub fn root(outdir: &str) -> PathBuf {
+    Path::new(outdir).join(".cache").join("derivations")
+}
+
+/// Attribute names may contain `/` or other characters that don't belong in
+/// a file name, so anything unusual is percent-encoded.
+fn file_name(attribute: &str) -> String {
+    let mut name = String::new();
+    for (i, byte) in attribute.bytes().enumerate() {
+        match byte {
+            b'.' if i == 0 => name.push_str("%2E"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'_' | b'+' | b'-' => {
+                name.push(byte as char)
+            }
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+impl DerivationCache {
+    pub fn new(outdir: &str, nixpkgs_path: &str, system: Option<&str>) -> Self {
+        let mut key = Path::new(nixpkgs_path).file_name().map_or_else(
+            || file_name(nixpkgs_path),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        if let Some(system) = system {
+            key.push('-');
+            key.push_str(system);
+        }
+        DerivationCache {
+            dir: root(outdir).join(key),
+        }
+    }
+
+    pub fn get(&self, attribute: &str) -> Option<String> {
+        fs::read_to_string(self.dir.join(file_name(attribute))).ok()
+    }
+
+    /// Stores the output for `attribute`, through a temporary file so a
+    /// killed run never leaves a truncated entry behind.
+    pub fn put(&self, attribute: &str, derivation_json: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(file_name(attribute));
+        let partial = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&partial, derivation_json)?;
+        fs::rename(&partial, &path)
+    }
+}
@@ -0,0 +1,64 @@
+use thiserror::Error;
+
+/// Why a run stopped before generating the vault.
+#[derive(Debug, Error)]
+pub enum VaultError {
+    /// `nix-instantiate` could not fetch the requested nixpkgs.
+    #[error("nix-instantiate failed: {0}")]
+    NixInstantiateFailed(String),
+
+    /// The fetched tree doesn't look like nixpkgs.
+    #[error("invalid nixpkgs repository: {0}")]
+    InvalidNixpkgs(String),
+
+    /// A JSON input, e.g. `packages.json`, could not be parsed.
+    #[error("failed to parse {path}")]
+    JsonParse {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    /// Reading or writing a file failed.
+    #[error("{context}")]
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+
+    /// The output directory holds files the run isn't allowed to overwrite.
+    #[error("output directory {0} already exists and is not empty")]
+    OutputDirectoryNotEmpty(String),
+
+    /// The config file isn't valid TOML or sets an option wrongly.
+    #[error("invalid config {path}: {message}")]
+    InvalidConfig { path: String, message: String },
+
+    /// Overwriting an existing vault was declined, or nobody could be asked.
+    #[error("aborted: {0}")]
+    Aborted(String),
+
+    /// Any other failure.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl VaultError {
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        VaultError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Process exit code for the error, so scripts can tell failures apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            VaultError::Other(_) => 1,
+            VaultError::OutputDirectoryNotEmpty(_) | VaultError::Aborted(_) => 2,
+            VaultError::NixInstantiateFailed(_) | VaultError::InvalidNixpkgs(_) => 3,
+            VaultError::JsonParse { .. } => 4,
+            VaultError::Io { .. } => 5,
+            VaultError::InvalidConfig { .. } => 6,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::derivation_cache::DerivationCache;
+#[cfg(feature = "parquet")]
+use crate::parquet;
+use crate::rdf::{self, RdfSyntax};
+use crate::{
+    apply_raw_derivation, find_packages_json, load_packages_json, package_info_from_meta,
+    redact_emails, Args, PackageInfo,
+};
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+#[cfg(feature = "parquet")]
+use colored::Colorize;
+use serde_json::Value;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+    #[command(subcommand)]
+    format: ExportFormat,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ExportFormat {
+    /// Comma-separated values, quoted where needed
+    Csv(TableArgs),
+    /// Tab-separated values, with tabs and line breaks in values turned into spaces
+    Tsv(TableArgs),
+    /// Packages, licenses, maintainers and dependencies as RDF, using DOAP,
+    /// FOAF and SPDX license URIs
+    Rdf(RdfArgs),
+    /// A packages table and a dependency edges table as Parquet files, e.g.
+    /// for DuckDB or Polars
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct TableArgs {
+    /// Columns to write, in this order
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "name,version,license,broken,maintainer-count,dependency-count,platforms"
+    )]
+    columns: Vec<Column>,
+
+    /// Write the table to this file instead of stdout
+    #[arg(long, short = 'o', value_name = "PATH")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RdfArgs {
+    /// Serialization to write
+    #[arg(long, value_enum, default_value = "turtle")]
+    syntax: RdfSyntax,
+
+    /// Write the triples to this file instead of stdout
+    #[arg(long, short = 'o', value_name = "PATH")]
+    output: Option<String>,
+}
+
+#[cfg(feature = "parquet")]
+#[derive(clap::Args, Debug)]
+struct ParquetArgs {
+    /// Directory to write packages.parquet and dependencies.parquet to
+    #[arg(long, short = 'o', value_name = "DIR", default_value = ".")]
+    output: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Column {
+    /// Attribute path in packages.json
+    Attribute,
+    Name,
+    Version,
+    License,
+    Available,
+    Broken,
+    /// Handles of the maintainers, separated by spaces
+    Maintainers,
+    MaintainerCount,
+    /// Empty for packages without a cached derivation
+    DependencyCount,
+    /// Supported platforms, separated by spaces
+    Platforms,
+    Homepage,
+    Description,
+    DrvPath,
+}
+
+impl Column {
+    fn header(self) -> String {
+        self.to_possible_value()
+            .expect("no column is skipped")
+            .get_name()
+            .replace('-', "_")
+    }
+
+    fn value(self, package_info: &PackageInfo, has_derivation: bool) -> String {
+        match self {
+            Column::Attribute => package_info.attribute.clone(),
+            Column::Name => package_info.name.clone(),
+            Column::Version => package_info.version.clone(),
+            Column::License => package_info.license_short_name.clone(),
+            Column::Available => package_info.available.to_string(),
+            Column::Broken => package_info.broken.to_string(),
+            Column::Maintainers => package_info
+                .maintainers
+                .iter()
+                .map(|maintainer| maintainer.handle())
+                .collect::<Vec<_>>()
+                .join(" "),
+            Column::MaintainerCount => package_info.maintainers.len().to_string(),
+            Column::DependencyCount if has_derivation => {
+                package_info.dependencies.len().to_string()
+            }
+            Column::DependencyCount => String::new(),
+            Column::Platforms => package_info.platforms.join(" "),
+            Column::Homepage => package_info.homepage.clone().unwrap_or_default(),
+            Column::Description => package_info.description.clone().unwrap_or_default(),
+            Column::DrvPath => package_info.drv_path.clone(),
+        }
+    }
+}
+
+/// A CSV field, quoted if it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A TSV field; the format has no quoting, so separators become spaces.
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Nixpkgs store path the last run in `outdir` evaluated, which keys its
+/// derivation cache.
+fn cached_nixpkgs_path(outdir: &str) -> Option<String> {
+    let data = fs::read_to_string(format!("{}/summary.json", outdir)).ok()?;
+    let summary: Value = serde_json::from_str(&data).ok()?;
+    summary["nixpkgs_path"].as_str().map(str::to_string)
+}
+
+/// A package of packages.json, as the subcommands reading a vault see it.
+pub struct CachedPackage {
+    pub package_info: PackageInfo,
+    /// Whether its derivation was found in the derivation cache
+    pub has_derivation: bool,
+    /// SPDX identifiers of `meta.license`, for licenses that have one
+    pub spdx_ids: Vec<String>,
+}
+
+/// SPDX identifiers of `meta.license`, which is a license set, a list of
+/// them, or occasionally a plain string without one.
+fn spdx_ids(license: &Value) -> Vec<String> {
+    match license {
+        Value::Array(licenses) => licenses.iter().flat_map(spdx_ids).collect(),
+        Value::Object(_) => license["spdxId"]
+            .as_str()
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Every package in the packages.json of `--outdir`, sorted by attribute.
+/// Derivation fields are only filled in for packages an earlier run
+/// evaluated.
+pub fn cached_packages(args: &Args) -> anyhow::Result<Vec<CachedPackage>> {
+    let packages_json_path = find_packages_json(&args.outdir).ok_or_else(|| {
+        anyhow!(
+            "{} has no packages.json, generate a vault first",
+            args.outdir
+        )
+    })?;
+    let parsed_json = load_packages_json(&packages_json_path)?;
+    let packages = parsed_json["packages"]
+        .as_object()
+        .ok_or_else(|| anyhow!("{} has no \"packages\" object", packages_json_path))?;
+    let cache = cached_nixpkgs_path(&args.outdir).map(|nixpkgs_path| {
+        DerivationCache::new(&args.outdir, &nixpkgs_path, args.system.as_deref())
+    });
+
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let info = &packages[name.as_str()];
+            let mut package_info = package_info_from_meta(name, info);
+            if args.redact_emails {
+                redact_emails(&mut package_info);
+            }
+            let has_derivation = cache
+                .as_ref()
+                .and_then(|cache| cache.get(name))
+                .is_some_and(|derivation| {
+                    apply_raw_derivation(derivation, &mut package_info).is_ok()
+                });
+            CachedPackage {
+                package_info,
+                has_derivation,
+                spdx_ids: spdx_ids(&info["meta"]["license"]),
+            }
+        })
+        .collect())
+}
+
+/// `path`, or stdout if there is none.
+fn writer(output: Option<&str>) -> anyhow::Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(path) => Box::new(BufWriter::new(
+            fs::File::create(path).with_context(|| format!("failed to create {}", path))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
+/// Writes the packages in the packages.json of `--outdir`, one row each for
+/// the tables.
+pub fn run(export_args: &ExportArgs, args: &Args) -> anyhow::Result<()> {
+    let (table_args, separator, field): (_, _, fn(&str) -> String) = match &export_args.format {
+        ExportFormat::Csv(table_args) => (table_args, ",", csv_field),
+        ExportFormat::Tsv(table_args) => (table_args, "\t", tsv_field),
+        ExportFormat::Rdf(rdf_args) => {
+            let packages = cached_packages(args)?;
+            let mut writer = writer(rdf_args.output.as_deref())?;
+            rdf::write(&mut writer, rdf_args.syntax, &packages)?;
+            writer.flush()?;
+            return Ok(());
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet(parquet_args) => {
+            let packages = cached_packages(args)?;
+            for path in parquet::write(&parquet_args.output, &packages)? {
+                println!("{} {}", "✅ Parquet file written to:".green().bold(), path);
+            }
+            return Ok(());
+        }
+    };
+    let packages = cached_packages(args)?;
+
+    let mut writer = writer(table_args.output.as_deref())?;
+    let header: Vec<String> = table_args
+        .columns
+        .iter()
+        .map(|column| column.header())
+        .collect();
+    writeln!(writer, "{}", header.join(separator))?;
+
+    for package in &packages {
+        let row: Vec<String> = table_args
+            .columns
+            .iter()
+            .map(|column| field(&column.value(&package.package_info, package.has_derivation)))
+            .collect();
+        writeln!(writer, "{}", row.join(separator))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Packages whose evaluation failed in earlier runs, so later runs against
+/// the same nixpkgs can skip them. Stored as `failed-cache.json`.
+#[derive(Serialize, Deserialize)]
+pub struct FailedCache {
+    revision: String,
+    /// Fetched store path; a different one means a different nixpkgs
+    nixpkgs_path: String,
+    /// Attribute name -> error class
+    packages: BTreeMap<String, String>,
+}
+
+pub fn path(outdir: &str) -> String {
+    format!("{}/failed-cache.json", outdir)
+}
+
+impl FailedCache {
+    pub fn new(revision: &str, nixpkgs_path: &str) -> Self {
+        FailedCache {
+            revision: revision.to_string(),
+            nixpkgs_path: nixpkgs_path.to_string(),
+            packages: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the cache of `outdir`, or starts an empty one if there is none,
+    /// it can't be read, or it was written for another nixpkgs.
+    pub fn load(outdir: &str, revision: &str, nixpkgs_path: &str) -> Self {
+        fs::read_to_string(path(outdir))
+            .ok()
+            .and_then(|data| serde_json::from_str::<FailedCache>(&data).ok())
+            .filter(|cache| cache.nixpkgs_path == nixpkgs_path)
+            .unwrap_or_else(|| FailedCache::new(revision, nixpkgs_path))
+    }
+
+    pub fn contains(&self, package: &str) -> bool {
+        self.packages.contains_key(package)
+    }
+
+    /// Forgets a package that is processed again in this run.
+    pub fn remove(&mut self, package: &str) {
+        self.packages.remove(package);
+    }
+
+    pub fn insert(&mut self, package: String, class: String) {
+        self.packages.insert(package, class);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+}
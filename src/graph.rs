@@ -0,0 +1,725 @@
+use crate::{export, html, Args, PackageInfo};
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::Write;
+
+#[derive(clap::Args, Debug)]
+pub struct GraphArgs {
+    /// Format of the graph
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+
+    /// Only the transitive dependencies of this package, by attribute path or name
+    #[arg(long, value_name = "PACKAGE")]
+    root: Option<String>,
+
+    /// Stop this many dependency levels below --root
+    #[arg(long, value_name = "N", requires = "root")]
+    depth: Option<usize>,
+
+    /// Write the graph to this file instead of stdout
+    #[arg(long, short = 'o', value_name = "PATH")]
+    output: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// GraphML, for yEd and Gephi, with version, license and broken node attributes
+    Graphml,
+    /// GEXF, for Gephi, with version, license and broken node attributes
+    Gexf,
+}
+
+impl GraphFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Mermaid => "mmd",
+            GraphFormat::Graphml => "graphml",
+            GraphFormat::Gexf => "gexf",
+        }
+    }
+}
+
+struct GraphNode {
+    attribute: String,
+    name: String,
+    version: String,
+    license: String,
+    broken: bool,
+    dependencies: Vec<String>,
+}
+
+/// Dependency edges between derivations collected during a run, keyed by drv
+/// path.
+#[derive(Default)]
+pub struct DependencyGraph {
+    nodes: HashMap<String, GraphNode>,
+}
+
+pub struct Edge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    /// The edge points back at a derivation that is still being walked,
+    /// i.e. following it would loop forever.
+    pub back_edge: bool,
+}
+
+/// Result of walking the dependency graph from a root package.
+pub struct Walk<'a> {
+    pub edges: Vec<Edge<'a>>,
+    /// Dependencies whose subtree was pruned with `--exclude-deps-of`.
+    pub collapsed: HashSet<&'a str>,
+}
+
+impl DependencyGraph {
+    pub fn insert(&mut self, package_info: &PackageInfo) {
+        self.nodes.insert(
+            package_info.drv_path.clone(),
+            GraphNode {
+                attribute: package_info.attribute.clone(),
+                name: package_info.name.clone(),
+                version: package_info.version.clone(),
+                license: package_info.license_short_name.clone(),
+                broken: package_info.broken,
+                dependencies: package_info.dependencies.clone(),
+            },
+        );
+    }
+
+    /// Processed packages in the package set `set`, e.g. `python3Packages`,
+    /// sorted by attribute path.
+    pub fn members(&self, set: &str) -> Vec<&str> {
+        let prefix = format!("{}.", set);
+        let mut members: Vec<(&str, &str)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.attribute.starts_with(&prefix))
+            .map(|(drv_path, node)| (node.attribute.as_str(), drv_path.as_str()))
+            .collect();
+        members.sort_unstable();
+        members.into_iter().map(|(_, drv_path)| drv_path).collect()
+    }
+
+    /// Looks a processed package up by attribute path or display name.
+    pub fn find_by_name(&self, name: &str) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|(_, node)| node.attribute == name || node.name == name)
+            .map(|(drv_path, _)| drv_path.as_str())
+    }
+
+    /// Human-readable label for a derivation: the package name when it was
+    /// processed in this run, otherwise the name part of the store path.
+    pub fn label<'a>(&'a self, drv_path: &'a str) -> &'a str {
+        match self.nodes.get(drv_path) {
+            Some(node) => &node.name,
+            None => store_path_name(drv_path),
+        }
+    }
+
+    /// Direct dependencies of every node, keyed and listed by label. Nodes
+    /// that only ever appear as a dependency get an empty entry.
+    pub fn adjacency(&self) -> BTreeMap<&str, BTreeSet<&str>> {
+        let mut adjacency: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (drv_path, node) in &self.nodes {
+            for dependency in &node.dependencies {
+                adjacency.entry(self.label(dependency)).or_default();
+            }
+            adjacency
+                .entry(self.label(drv_path))
+                .or_default()
+                .extend(node.dependencies.iter().map(|d| self.label(d)));
+        }
+        adjacency
+    }
+
+    /// Processed packages that depend directly on each derivation, sorted
+    /// by label.
+    pub fn dependents(&self) -> HashMap<&str, Vec<&str>> {
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (drv_path, node) in &self.nodes {
+            for dependency in &node.dependencies {
+                dependents.entry(dependency).or_default().push(drv_path);
+            }
+        }
+        for list in dependents.values_mut() {
+            list.sort_by_key(|drv_path| (self.label(drv_path), *drv_path));
+            list.dedup();
+        }
+        dependents
+    }
+
+    /// Direct dependencies of a processed package, none for others.
+    pub fn direct_dependencies(&self, drv_path: &str) -> &[String] {
+        self.nodes
+            .get(drv_path)
+            .map_or(&[], |node| node.dependencies.as_slice())
+    }
+
+    /// Whether `drv_path` is the package `pattern` refers to, by attribute
+    /// path, display name, or store path name with or without its version.
+    fn matches(&self, drv_path: &str, pattern: &str) -> bool {
+        if let Some(node) = self.nodes.get(drv_path) {
+            if node.attribute == pattern || node.name == pattern {
+                return true;
+            }
+        }
+        let name = store_path_name(drv_path);
+        name == pattern || strip_version(name) == pattern
+    }
+
+    /// Fewest dependency edges from `root` to every derivation within
+    /// `max_depth` of it.
+    fn distances<'a>(&'a self, root: &'a str, max_depth: usize) -> HashMap<&'a str, usize> {
+        let mut distances = HashMap::from([(root, 0)]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(node) = queue.pop_front() {
+            let distance = distances[node];
+            if distance == max_depth {
+                continue;
+            }
+            for dependency in self.direct_dependencies(node) {
+                if !distances.contains_key(dependency.as_str()) {
+                    distances.insert(dependency, distance + 1);
+                    queue.push_back(dependency);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Walks the transitive dependencies of `root` depth-first, returning
+    /// every edge exactly once. Cycles are broken by marking the edge that
+    /// closes them as a back edge instead of descending into it again.
+    /// Dependencies matching `excluded` are kept as leaves without walking
+    /// into their own dependencies, as are those `max_depth` edges away.
+    pub fn walk<'a>(
+        &'a self,
+        root: &'a str,
+        excluded: &[String],
+        max_depth: Option<usize>,
+    ) -> Walk<'a> {
+        // Depth-first order would cut off a derivation reached by a long
+        // path first, so the depth is that of its shortest path
+        let distances = max_depth.map(|max_depth| self.distances(root, max_depth));
+        let mut edges = Vec::new();
+        let mut collapsed = HashSet::new();
+        let mut visited: HashSet<&str> = HashSet::from([root]);
+        let mut on_path: HashSet<&str> = HashSet::from([root]);
+        let mut stack: Vec<(&str, usize)> = vec![(root, 0)];
+
+        while let Some(&(node, index)) = stack.last() {
+            let dependencies = self.direct_dependencies(node);
+            let Some(dependency) = dependencies.get(index) else {
+                on_path.remove(node);
+                stack.pop();
+                continue;
+            };
+            if let Some(top) = stack.last_mut() {
+                top.1 += 1;
+            }
+
+            let dependency = dependency.as_str();
+            let back_edge = on_path.contains(dependency);
+            edges.push(Edge {
+                from: node,
+                to: dependency,
+                back_edge,
+            });
+            if !back_edge && visited.insert(dependency) {
+                if excluded.iter().any(|e| self.matches(dependency, e)) {
+                    collapsed.insert(dependency);
+                    continue;
+                }
+                if let (Some(distances), Some(max_depth)) = (&distances, max_depth) {
+                    if distances.get(dependency).is_none_or(|&d| d >= max_depth) {
+                        continue;
+                    }
+                }
+                on_path.insert(dependency);
+                stack.push((dependency, 0));
+            }
+        }
+
+        Walk { edges, collapsed }
+    }
+}
+
+/// `/nix/store/<hash>-glibc-2.39.drv` -> `glibc-2.39`
+pub fn store_path_name(path: &str) -> &str {
+    let base = path.strip_prefix("/nix/store/").unwrap_or(path);
+    let base = base.strip_suffix(".drv").unwrap_or(base);
+    base.split_once('-').map_or(base, |(_, name)| name)
+}
+
+/// `glibc-2.39` -> `glibc`, cutting at the first `-` followed by a digit.
+fn strip_version(name: &str) -> &str {
+    name.char_indices()
+        .find(|&(i, c)| c == '-' && name[i + 1..].starts_with(|c: char| c.is_ascii_digit()))
+        .map_or(name, |(i, _)| &name[..i])
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn render(
+    format: GraphFormat,
+    graph: &DependencyGraph,
+    root: &str,
+    excluded: &[String],
+    max_depth: Option<usize>,
+) -> String {
+    let walk = graph.walk(root, excluded, max_depth);
+    match format {
+        GraphFormat::Dot => render_dot(graph, root, &walk),
+        GraphFormat::Mermaid => render_mermaid(graph, root, &walk),
+        GraphFormat::Graphml | GraphFormat::Gexf => {
+            let nodes = walk_nodes(root, &walk);
+            let edges: Vec<_> = walk
+                .edges
+                .iter()
+                .map(|edge| (edge.from, edge.to, edge.back_edge))
+                .collect();
+            render_xml(format, graph, &nodes, &edges, &walk.collapsed)
+        }
+    }
+}
+
+/// `root` and every derivation the walk reached, in the order reached.
+fn walk_nodes<'a>(root: &'a str, walk: &Walk<'a>) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    std::iter::once(root)
+        .chain(walk.edges.iter().flat_map(|e| [e.from, e.to]))
+        .filter(|drv_path| seen.insert(*drv_path))
+        .collect()
+}
+
+fn render_dot(graph: &DependencyGraph, root: &str, walk: &Walk) -> String {
+    let edges = &walk.edges;
+    let mut content = String::new();
+    content.push_str(&format!("digraph \"{}\" {{\n", escape(graph.label(root))));
+    content.push_str("  rankdir=LR;\n");
+    content.push_str("  node [shape=box];\n");
+
+    for drv_path in walk_nodes(root, walk) {
+        content.push_str(&format!(
+            "  \"{}\" [label=\"{}\"{}];\n",
+            escape(drv_path),
+            escape(graph.label(drv_path)),
+            if walk.collapsed.contains(drv_path) {
+                ", shape=box3d, style=dashed"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    for edge in edges {
+        content.push_str(&format!(
+            "  \"{}\" -> \"{}\"{};\n",
+            escape(edge.from),
+            escape(edge.to),
+            if edge.back_edge {
+                " [style=dashed, color=red, label=\"cycle\"]"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    content.push_str("}\n");
+    content
+}
+
+/// Every processed derivation and its direct dependencies, and the edges
+/// between them, sorted by drv path so reruns diff cleanly.
+fn full_graph(graph: &DependencyGraph) -> (BTreeSet<&str>, Vec<(&str, &str)>) {
+    let mut edges: Vec<(&str, &str)> = graph
+        .nodes
+        .iter()
+        .flat_map(|(drv_path, node)| {
+            node.dependencies
+                .iter()
+                .map(move |dependency| (drv_path.as_str(), dependency.as_str()))
+        })
+        .collect();
+    edges.sort_unstable();
+    edges.dedup();
+    let nodes: BTreeSet<&str> = graph
+        .nodes
+        .keys()
+        .map(String::as_str)
+        .chain(edges.iter().map(|&(_, to)| to))
+        .collect();
+    (nodes, edges)
+}
+
+/// The whole graph as DOT.
+pub fn render_full_dot(graph: &DependencyGraph) -> String {
+    let (nodes, edges) = full_graph(graph);
+    let mut content = String::from("digraph \"nixpkgs\" {\n");
+    content.push_str("  rankdir=LR;\n");
+    content.push_str("  node [shape=box];\n");
+    for drv_path in nodes {
+        content.push_str(&format!(
+            "  \"{}\" [label=\"{}\"];\n",
+            escape(drv_path),
+            escape(graph.label(drv_path))
+        ));
+    }
+    for (from, to) in edges {
+        content.push_str(&format!("  \"{}\" -> \"{}\";\n", escape(from), escape(to)));
+    }
+    content.push_str("}\n");
+    content
+}
+
+fn render_mermaid(graph: &DependencyGraph, root: &str, walk: &Walk) -> String {
+    let edges = &walk.edges;
+    let mut content = String::from("flowchart LR\n");
+
+    // Mermaid ids can't contain most store path characters, so number them.
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    for drv_path in std::iter::once(root).chain(edges.iter().flat_map(|e| [e.from, e.to])) {
+        let next_id = ids.len();
+        ids.entry(drv_path).or_insert_with(|| {
+            // Collapsed subtrees use the subroutine shape `[[...]]`
+            let (open, close) = if walk.collapsed.contains(drv_path) {
+                ("[[", "]]")
+            } else {
+                ("[", "]")
+            };
+            content.push_str(&format!(
+                "  n{}{}\"{}\"{}\n",
+                next_id,
+                open,
+                graph.label(drv_path).replace('"', "#quot;"),
+                close
+            ));
+            next_id
+        });
+    }
+
+    for edge in edges {
+        content.push_str(&format!(
+            "  n{} {} n{}\n",
+            ids[edge.from],
+            if edge.back_edge { "-.->|cycle|" } else { "-->" },
+            ids[edge.to]
+        ));
+    }
+
+    content
+}
+
+/// The whole graph as GraphML or GEXF.
+fn render_full_xml(format: GraphFormat, graph: &DependencyGraph) -> String {
+    let (nodes, edges) = full_graph(graph);
+    let nodes: Vec<&str> = nodes.into_iter().collect();
+    let edges: Vec<_> = edges
+        .into_iter()
+        .map(|(from, to)| (from, to, false))
+        .collect();
+    render_xml(format, graph, &nodes, &edges, &HashSet::new())
+}
+
+/// Node attributes of the XML formats, as (id, name, type).
+const NODE_ATTRIBUTES: &[(&str, &str, &str)] = &[
+    ("d0", "attribute", "string"),
+    ("d1", "version", "string"),
+    ("d2", "license", "string"),
+    ("d3", "broken", "boolean"),
+    ("d4", "collapsed", "boolean"),
+];
+
+/// Values of `NODE_ATTRIBUTES` for `drv_path`: processed packages have all
+/// but `collapsed`, other derivations at most that.
+fn node_attributes(
+    graph: &DependencyGraph,
+    drv_path: &str,
+    collapsed: &HashSet<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut values = Vec::new();
+    if let Some(node) = graph.nodes.get(drv_path) {
+        values.push(("d0", node.attribute.clone()));
+        values.push(("d1", node.version.clone()));
+        values.push(("d2", node.license.clone()));
+        values.push(("d3", node.broken.to_string()));
+    }
+    if collapsed.contains(drv_path) {
+        values.push(("d4", "true".to_string()));
+    }
+    values
+}
+
+/// GraphML or GEXF of `nodes`, identified by drv path, and `edges`, as
+/// (from, to, back edge). Back edges get `cycle` set.
+fn render_xml(
+    format: GraphFormat,
+    graph: &DependencyGraph,
+    nodes: &[&str],
+    edges: &[(&str, &str, bool)],
+    collapsed: &HashSet<&str>,
+) -> String {
+    let mut content = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    if format == GraphFormat::Graphml {
+        content.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        content.push_str(
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+        );
+        for (id, name, kind) in NODE_ATTRIBUTES {
+            content.push_str(&format!(
+                "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"{}\"/>\n",
+                id, name, kind
+            ));
+        }
+        content.push_str(
+            "  <key id=\"cycle\" for=\"edge\" attr.name=\"cycle\" attr.type=\"boolean\"/>\n",
+        );
+        content.push_str("  <graph id=\"nixpkgs\" edgedefault=\"directed\">\n");
+        for drv_path in nodes {
+            content.push_str(&format!("    <node id=\"{}\">\n", html::escape(drv_path)));
+            content.push_str(&format!(
+                "      <data key=\"label\">{}</data>\n",
+                html::escape(graph.label(drv_path))
+            ));
+            for (key, value) in node_attributes(graph, drv_path, collapsed) {
+                content.push_str(&format!(
+                    "      <data key=\"{}\">{}</data>\n",
+                    key,
+                    html::escape(&value)
+                ));
+            }
+            content.push_str("    </node>\n");
+        }
+        for (from, to, back_edge) in edges {
+            let (source, target) = (html::escape(from), html::escape(to));
+            if *back_edge {
+                content.push_str(&format!(
+                    "    <edge source=\"{}\" target=\"{}\"><data key=\"cycle\">true</data></edge>\n",
+                    source, target
+                ));
+            } else {
+                content.push_str(&format!(
+                    "    <edge source=\"{}\" target=\"{}\"/>\n",
+                    source, target
+                ));
+            }
+        }
+        content.push_str("  </graph>\n</graphml>\n");
+    } else {
+        content.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+        content.push_str("  <graph defaultedgetype=\"directed\">\n");
+        content.push_str("    <attributes class=\"node\">\n");
+        for (id, name, kind) in NODE_ATTRIBUTES {
+            content.push_str(&format!(
+                "      <attribute id=\"{}\" title=\"{}\" type=\"{}\"/>\n",
+                id, name, kind
+            ));
+        }
+        content.push_str("    </attributes>\n");
+        content.push_str("    <attributes class=\"edge\">\n");
+        content.push_str("      <attribute id=\"cycle\" title=\"cycle\" type=\"boolean\"/>\n");
+        content.push_str("    </attributes>\n");
+        content.push_str("    <nodes>\n");
+        for drv_path in nodes {
+            let attributes = node_attributes(graph, drv_path, collapsed);
+            let open = format!(
+                "      <node id=\"{}\" label=\"{}\"",
+                html::escape(drv_path),
+                html::escape(graph.label(drv_path))
+            );
+            if attributes.is_empty() {
+                content.push_str(&format!("{}/>\n", open));
+                continue;
+            }
+            content.push_str(&format!("{}>\n        <attvalues>\n", open));
+            for (id, value) in attributes {
+                content.push_str(&format!(
+                    "          <attvalue for=\"{}\" value=\"{}\"/>\n",
+                    id,
+                    html::escape(&value)
+                ));
+            }
+            content.push_str("        </attvalues>\n      </node>\n");
+        }
+        content.push_str("    </nodes>\n    <edges>\n");
+        for (index, (from, to, back_edge)) in edges.iter().enumerate() {
+            let open = format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\"",
+                index,
+                html::escape(from),
+                html::escape(to)
+            );
+            if *back_edge {
+                content.push_str(&format!(
+                    "{}>\n        <attvalues><attvalue for=\"cycle\" value=\"true\"/></attvalues>\n      </edge>\n",
+                    open
+                ));
+            } else {
+                content.push_str(&format!("{}/>\n", open));
+            }
+        }
+        content.push_str("    </edges>\n  </graph>\n</gexf>\n");
+    }
+    content
+}
+
+/// Prints the dependency graph of the packages in `--outdir` whose
+/// derivations are cached, or of `--root` alone.
+pub fn run(graph_args: &GraphArgs, args: &Args) -> anyhow::Result<()> {
+    let mut graph = DependencyGraph::default();
+    for export::CachedPackage {
+        package_info,
+        has_derivation,
+        ..
+    } in export::cached_packages(args)?
+    {
+        if has_derivation {
+            graph.insert(&package_info);
+        }
+    }
+    if graph.nodes.is_empty() {
+        return Err(anyhow!(
+            "{} has no cached derivations, generate a vault first",
+            args.outdir
+        ));
+    }
+
+    let content = match (&graph_args.root, graph_args.format) {
+        (Some(root), format) => {
+            let drv_path = graph
+                .find_by_name(root)
+                .ok_or_else(|| anyhow!("{} has no cached derivation for {}", args.outdir, root))?;
+            render(
+                format,
+                &graph,
+                drv_path,
+                &args.exclude_deps_of,
+                graph_args.depth,
+            )
+        }
+        (None, GraphFormat::Dot) => render_full_dot(&graph),
+        (None, format @ (GraphFormat::Graphml | GraphFormat::Gexf)) => {
+            render_full_xml(format, &graph)
+        }
+        (None, GraphFormat::Mermaid) => {
+            return Err(anyhow!(
+                "mermaid graphs of every package are too large to render, pass --root"
+            ))
+        }
+    };
+    match graph_args.output {
+        Some(ref path) => {
+            fs::write(path, content).with_context(|| format!("failed to write {}", path))?
+        }
+        None => std::io::stdout().write_all(content.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_info_from_meta;
+    use serde_json::json;
+
+    fn drv(name: &str) -> String {
+        format!("/nix/store/00000000000000000000000000000000-{}.drv", name)
+    }
+
+    /// A graph of processed packages, each given as its name and the names
+    /// of its dependencies.
+    fn graph(packages: &[(&str, &[&str])]) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for (name, dependencies) in packages {
+            let mut package_info = package_info_from_meta(name, &json!({ "meta": {} }));
+            package_info.drv_path = drv(name);
+            package_info.dependencies = dependencies.iter().map(|d| drv(d)).collect();
+            graph.insert(&package_info);
+        }
+        graph
+    }
+
+    #[test]
+    fn walk_marks_the_edge_closing_a_cycle() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        let root = drv("a");
+        let walk = graph.walk(&root, &[], None);
+
+        let edges: Vec<_> = walk
+            .edges
+            .iter()
+            .map(|e| (store_path_name(e.from), store_path_name(e.to), e.back_edge))
+            .collect();
+        assert_eq!(
+            edges,
+            [("a", "b", false), ("b", "c", false), ("c", "a", true)]
+        );
+    }
+
+    #[test]
+    fn walk_visits_shared_dependencies_once() {
+        // b and c both depend on d, which is not a cycle
+        let graph = graph(&[("a", &["b", "c"]), ("b", &["d"]), ("c", &["d"]), ("d", &[])]);
+        let root = drv("a");
+        let walk = graph.walk(&root, &[], None);
+
+        assert_eq!(walk.edges.len(), 4);
+        assert!(walk.edges.iter().all(|edge| !edge.back_edge));
+    }
+
+    #[test]
+    fn rendered_cycles_are_marked() {
+        let graph = graph(&[("a", &["b"]), ("b", &["a"])]);
+        let root = drv("a");
+
+        let dot = render(GraphFormat::Dot, &graph, &root, &[], None);
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [style=dashed, color=red, label=\"cycle\"];",
+            drv("b"),
+            drv("a")
+        )));
+        let mermaid = render(GraphFormat::Mermaid, &graph, &root, &[], None);
+        assert!(mermaid.contains("n1 -.->|cycle| n0"));
+    }
+
+    #[test]
+    fn excluded_dependencies_are_not_walked() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &[])]);
+        let root = drv("a");
+        let walk = graph.walk(&root, &["b".to_string()], None);
+
+        assert_eq!(walk.edges.len(), 1);
+        assert!(walk.collapsed.contains(drv("b").as_str()));
+    }
+
+    #[test]
+    fn full_dot_lists_every_node_and_edge_once() {
+        // d is only known as a dependency, under its store path name
+        let mut graph = graph(&[("b", &["d", "d"]), ("a", &["b", "d"])]);
+        graph.nodes.get_mut(&drv("a")).unwrap().name = "a \"quoted\"".to_string();
+
+        let expected = format!(
+            "digraph \"nixpkgs\" {{\n  rankdir=LR;\n  node [shape=box];\n\
+             \x20 \"{a}\" [label=\"a \\\"quoted\\\"\"];\n\
+             \x20 \"{b}\" [label=\"b\"];\n\
+             \x20 \"{d}\" [label=\"d\"];\n\
+             \x20 \"{a}\" -> \"{b}\";\n\
+             \x20 \"{a}\" -> \"{d}\";\n\
+             \x20 \"{b}\" -> \"{d}\";\n}}\n",
+            a = drv("a"),
+            b = drv("b"),
+            d = drv("d")
+        );
+        assert_eq!(render_full_dot(&graph), expected);
+    }
+}
@@ -0,0 +1,117 @@
+use pulldown_cmark::{html, Options, Parser};
+use std::fs;
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem;line-height:1.5}\
+code{background:#f3f3f3;padding:0 .2em;border-radius:3px}\
+table{border-collapse:collapse}td,th{border:1px solid #ddd;padding:.2em .5em}\
+blockquote{border-left:3px solid #ccc;margin-left:0;padding-left:1em;color:#555}\
+nav{margin-bottom:1rem}#search{width:100%;padding:.5em;font-size:1rem}";
+
+/// A package listed in `index.html`.
+pub struct IndexEntry {
+    pub name: String,
+    pub path: String,
+    pub description: Option<String>,
+}
+
+/// Escapes text for HTML and XML, inside elements and quoted attributes.
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `../` repeated once per directory of `relative_path`, leading from a
+/// page back to the site root.
+pub fn root_prefix(relative_path: &str) -> String {
+    "../".repeat(relative_path.matches('/').count())
+}
+
+/// Turns `[[target]]` wikilinks into markdown links to the target's page,
+/// which ends in `extension`. Targets containing `/` are paths from the site
+/// root (as written for `--layout attr-tree`), others are notes in the same
+/// directory.
+pub fn convert_wikilinks(markdown: &str, root: &str, extension: &str) -> String {
+    let mut converted = String::with_capacity(markdown.len());
+    let mut rest = markdown;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start..].find("]]") else {
+            break;
+        };
+        let target = &rest[start + 2..start + length];
+        let (target, label) = target
+            .split_once('|')
+            .unwrap_or((target, target.rsplit('/').next().unwrap_or(target)));
+        let href = if target.contains('/') {
+            format!("{}{}.{}", root, target, extension)
+        } else {
+            format!("{}.{}", target, extension)
+        };
+        converted.push_str(&rest[..start]);
+        converted.push_str(&format!("[{}](<{}>)", label, href));
+        rest = &rest[start + length + 2..];
+    }
+    converted.push_str(rest);
+    converted
+}
+
+/// The note without its YAML front matter, which has no place in a page.
+pub fn strip_front_matter(markdown: &str) -> &str {
+    markdown
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .map_or(markdown, |(_, body)| body)
+}
+
+/// Renders a markdown package note as a standalone page at `relative_path`.
+pub fn render_page(title: &str, markdown: &str, relative_path: &str) -> String {
+    let root = root_prefix(relative_path);
+    let markdown = convert_wikilinks(strip_front_matter(markdown), &root, "html");
+
+    let mut body = String::new();
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    html::push_html(&mut body, Parser::new_ext(&markdown, options));
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<nav><a href=\"{}index.html\">← All packages</a></nav>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        root,
+        body
+    )
+}
+
+/// Writes `index.html` listing every page, with a search box filtering the
+/// list by name and description.
+pub fn write_index(outdir: &str, entries: Vec<IndexEntry>) -> std::io::Result<String> {
+    let path = format!("{}/index.html", outdir);
+    fs::write(&path, render_index(entries))?;
+    Ok(path)
+}
+
+/// The index page listing `entries`, whose paths are relative to the root.
+pub fn render_index(mut entries: Vec<IndexEntry>) -> String {
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut items = String::new();
+    for entry in &entries {
+        items.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>{}</li>\n",
+            escape(&entry.path),
+            escape(&entry.name),
+            entry
+                .description
+                .as_ref()
+                .map_or(String::new(), |d| format!(" — {}", escape(d)))
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Nixpkgs packages</title>\n<style>{}</style>\n</head>\n<body>\n<h1>Nixpkgs packages ({})</h1>\n<input id=\"search\" type=\"search\" placeholder=\"Search packages…\" autofocus>\n<ul id=\"packages\">\n{}</ul>\n<script>\nconst items = [...document.querySelectorAll('#packages li')];\ndocument.getElementById('search').addEventListener('input', e => {{\n  const query = e.target.value.toLowerCase();\n  for (const item of items) item.hidden = !item.textContent.toLowerCase().includes(query);\n}});\n</script>\n</body>\n</html>\n",
+        STYLE,
+        entries.len(),
+        items
+    )
+}
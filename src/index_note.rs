@@ -0,0 +1,256 @@
+use crate::{front_matter_list, write_if_changed, GENERATED_FOOTER};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// A package listed in `index.md`.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct IndexNoteEntry {
+    pub name: String,
+    /// Note path relative to the vault, without extension
+    pub target: String,
+    pub broken: bool,
+    pub available: bool,
+}
+
+impl IndexNoteEntry {
+    /// Reads the entry back from a note an earlier run wrote, for packages
+    /// skipped with `--resume`.
+    pub fn from_note(outdir: &str, relative_path: &str) -> Option<Self> {
+        let content = fs::read_to_string(format!("{}/{}", outdir, relative_path)).ok()?;
+        let (front_matter, body) = content.strip_prefix("---\n")?.split_once("\n---\n")?;
+        let tags = front_matter_list(front_matter, "tags");
+        let has_tag = |tag: &str| tags.iter().any(|t| t == tag);
+        Some(IndexNoteEntry {
+            name: body
+                .lines()
+                .find_map(|line| line.strip_prefix("# "))?
+                .to_string(),
+            target: relative_path
+                .rsplit_once('.')
+                .map_or(relative_path, |(target, _)| target)
+                .to_string(),
+            broken: has_tag("broken"),
+            available: !has_tag("not-available"),
+        })
+    }
+}
+
+pub fn path(outdir: &str) -> String {
+    format!("{}/index.json", outdir)
+}
+
+/// Entries of `index.md` by attribute name, kept in `index.json` so a run
+/// that only processes some packages updates their entries instead of
+/// listing nothing but them.
+#[derive(Default, Serialize, Deserialize)]
+pub struct IndexState {
+    entries: BTreeMap<String, IndexNoteEntry>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl IndexState {
+    /// Reads the entries of the last run in `outdir`, none if it has no
+    /// state or an unreadable one.
+    pub fn load(outdir: &str) -> Self {
+        fs::read_to_string(path(outdir))
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Adds or replaces the entry of a package processed in this run.
+    pub fn insert(&mut self, package: &str, entry: IndexNoteEntry) {
+        if self.entries.get(package) != Some(&entry) {
+            self.entries.insert(package.to_string(), entry);
+            self.changed = true;
+        }
+    }
+
+    /// Drops the entries of packages `keep` rejects, e.g. those gone from
+    /// packages.json.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let before = self.entries.len();
+        self.entries.retain(|package, _| keep(package));
+        self.changed |= self.entries.len() != before;
+    }
+
+    /// Writes `index.md` unless no entry changed since it was last written.
+    /// Returns whether it was written.
+    pub fn write_index_note(&self, outdir: &str) -> Result<bool, std::io::Error> {
+        if !self.changed && Path::new(outdir).join("index.md").is_file() {
+            return Ok(false);
+        }
+        let entries: Vec<&IndexNoteEntry> = self.entries.values().collect();
+        generate_index_note(&entries, outdir)
+    }
+}
+
+/// Heading a package is listed under: its upper-cased first letter, or `#`.
+fn group(name: &str) -> String {
+    match name.chars().next() {
+        Some(first) if first.is_alphabetic() => first.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+/// Writes `index.md`, the entry point of the vault: counts, then a link to
+/// every note grouped by first letter. Returns whether the file changed.
+pub fn generate_index_note(
+    entries: &[&IndexNoteEntry],
+    outdir: &str,
+) -> Result<bool, std::io::Error> {
+    let mut groups: BTreeMap<String, Vec<&IndexNoteEntry>> = BTreeMap::new();
+    for &entry in entries {
+        groups.entry(group(&entry.name)).or_default().push(entry);
+    }
+
+    let mut content = String::from("# 📚 Nixpkgs packages\n\n");
+    content.push_str(&format!("- **Total**: {}\n", entries.len()));
+    content.push_str(&format!(
+        "- **Broken**: {}\n",
+        entries.iter().filter(|entry| entry.broken).count()
+    ));
+    content.push_str(&format!(
+        "- **Unavailable**: {}\n\n",
+        entries.iter().filter(|entry| !entry.available).count()
+    ));
+
+    for (heading, mut group) in groups {
+        group.sort_by(|a, b| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        content.push_str(&format!("## {}\n\n", heading));
+        for entry in group {
+            content.push_str(&format!("- [[{}|{}]]\n", entry.target, entry.name));
+        }
+        content.push('\n');
+    }
+
+    content.push_str(GENERATED_FOOTER);
+    content.push_str(&format!(
+        "{}*\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+
+    write_if_changed(&format!("{}/index.md", outdir), &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, broken: bool) -> IndexNoteEntry {
+        IndexNoteEntry {
+            name: name.to_string(),
+            target: format!("packages/{}", name),
+            broken,
+            available: true,
+        }
+    }
+
+    fn outdir(test: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("nixpkgs-vault-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn index_lists_every_package_with_counts() {
+        let outdir = outdir("index-note");
+        let entries = [
+            entry("hello", false),
+            entry("jq", true),
+            entry("Zlib", false),
+        ];
+        generate_index_note(&entries.iter().collect::<Vec<_>>(), &outdir).unwrap();
+
+        let index = fs::read_to_string(format!("{}/index.md", outdir)).unwrap();
+        assert!(index.contains("- **Total**: 3\n"));
+        assert!(index.contains("- **Broken**: 1\n"));
+        assert!(index.contains("- **Unavailable**: 0\n"));
+        assert!(index.contains("## H\n\n- [[packages/hello|hello]]\n"));
+        assert!(index.contains("## J\n\n- [[packages/jq|jq]]\n"));
+        assert!(index.contains("## Z\n\n- [[packages/Zlib|Zlib]]\n"));
+        fs::remove_dir_all(outdir).unwrap();
+    }
+
+    #[test]
+    fn later_runs_update_only_their_packages() {
+        let outdir = outdir("index-state");
+        let mut state = IndexState::default();
+        state.insert("hello", entry("hello", false));
+        state.insert("jq", entry("jq", false));
+        state.insert("curl", entry("curl", false));
+        state.write_index_note(&outdir).unwrap();
+        fs::write(path(&outdir), serde_json::to_string(&state).unwrap()).unwrap();
+
+        // A run that processed jq again, now broken, and a new package,
+        // after curl was removed from packages.json
+        let mut state = IndexState::load(&outdir);
+        state.insert("jq", entry("jq", true));
+        state.insert("ripgrep", entry("ripgrep", false));
+        state.retain(|package| package != "curl");
+        assert!(state.write_index_note(&outdir).unwrap());
+
+        let index = fs::read_to_string(format!("{}/index.md", outdir)).unwrap();
+        assert!(index.contains("- **Total**: 3\n"));
+        assert!(index.contains("- **Broken**: 1\n"));
+        assert!(index.contains("[[packages/hello|hello]]"));
+        assert!(index.contains("[[packages/ripgrep|ripgrep]]"));
+        assert!(!index.contains("curl"));
+        fs::remove_dir_all(outdir).unwrap();
+    }
+
+    #[test]
+    fn unchanged_entries_leave_the_index_alone() {
+        let outdir = outdir("index-unchanged");
+        let mut state = IndexState::default();
+        state.insert("hello", entry("hello", false));
+        state.write_index_note(&outdir).unwrap();
+        fs::write(path(&outdir), serde_json::to_string(&state).unwrap()).unwrap();
+
+        let mut state = IndexState::load(&outdir);
+        state.insert("hello", entry("hello", false));
+        state.retain(|_| true);
+        assert!(!state.write_index_note(&outdir).unwrap());
+        fs::remove_dir_all(outdir).unwrap();
+    }
+
+    #[test]
+    fn names_group_by_first_letter() {
+        assert_eq!(group("hello"), "H");
+        assert_eq!(group("Hello"), "H");
+        assert_eq!(group("ñ"), "Ñ");
+        assert_eq!(group("2048-in-terminal"), "#");
+        assert_eq!(group("_1password"), "#");
+        assert_eq!(group(""), "#");
+    }
+
+    #[test]
+    fn notes_of_earlier_runs_give_their_entries() {
+        let outdir = outdir("index-from-note");
+        fs::create_dir_all(format!("{}/packages", outdir)).unwrap();
+        fs::write(
+            format!("{}/packages/jq.md", outdir),
+            "---\naliases:\n  - jq\ntags:\n  - broken\n  - not-available\n---\n\n# jq\n",
+        )
+        .unwrap();
+
+        let entry = IndexNoteEntry::from_note(&outdir, "packages/jq.md").unwrap();
+        assert_eq!(entry.name, "jq");
+        assert_eq!(entry.target, "packages/jq");
+        assert!(entry.broken);
+        assert!(!entry.available);
+        assert!(IndexNoteEntry::from_note(&outdir, "packages/missing.md").is_none());
+        fs::remove_dir_all(outdir).unwrap();
+    }
+}
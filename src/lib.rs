@@ -0,0 +1,4619 @@
+//! Generates an Obsidian vault with one note per nixpkgs package.
+//!
+//! The command line is a thin wrapper around [`parse_args`], which merges the
+//! config file into the [`Args`], and [`run`], which takes them and reports
+//! failures as a [`VaultError`].
+
+use anyhow::{anyhow, bail, Context};
+use chrono::Utc;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use rust_embed::RustEmbed;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+mod bench;
+mod canvas;
+mod child;
+mod config;
+#[cfg(feature = "db")]
+mod db;
+mod derivation_cache;
+mod error;
+mod export;
+mod failed_cache;
+mod graph;
+mod html;
+mod index_note;
+mod logging;
+mod logseq;
+mod manifest;
+mod mdbook;
+mod ndjson;
+mod note_index;
+mod note_template;
+mod ordered;
+mod org;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod profile;
+mod progress;
+mod prune;
+mod query;
+mod rdf;
+mod refresh;
+mod semaphore;
+#[cfg(feature = "serve")]
+mod serve;
+mod site;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod typst;
+mod zettel;
+
+pub use config::parse_args;
+use derivation_cache::DerivationCache;
+pub use error::VaultError;
+use failed_cache::FailedCache;
+use graph::{DependencyGraph, GraphFormat};
+use note_template::NoteTemplate;
+use ordered::OrderedSink;
+use profile::{ProfileReport, Profiler};
+use progress::{JsonProgress, ProgressFormat};
+use semaphore::Semaphore;
+use site::FrontmatterStyle;
+
+/// `meta.priority` assumed by nix-env when a package doesn't set one.
+const DEFAULT_PRIORITY: i64 = 5;
+
+/// Start of the timestamp footer closing every markdown note.
+const GENERATED_FOOTER: &str = "---\n*Generated on ";
+/// Error class of packages `nix derivation show` failed for, the only ones
+/// remembered in `failed-cache.json`.
+const EVALUATION_FAILED: &str = "evaluation failed";
+/// Error class of attributes nixpkgs doesn't have, also remembered.
+const DERIVATION_NOT_FOUND: &str = "no such derivation";
+
+#[derive(RustEmbed)]
+#[folder = "template/"]
+struct TemplateAssets;
+
+/// Configuration of a run, parsed from the command line.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Read options the command line doesn't set from this TOML file
+    /// (default: ./nixpkgs-vault.toml, if it exists)
+    #[arg(long, value_name = "FILE", env = "NIXPKGS_VAULT_CONFIG")]
+    config: Option<String>,
+
+    /// output directory
+    #[arg(
+        short,
+        long,
+        default_value = "nixpkgs-vault",
+        env = "NIXPKGS_VAULT_OUTDIR"
+    )]
+    outdir: String,
+
+    /// nixpkgs git revision
+    #[arg(
+        short,
+        long,
+        default_value = "nixos-unstable",
+        env = "NIXPKGS_VAULT_REVISION"
+    )]
+    revision: String,
+
+    /// nixpkgs git url
+    #[arg(
+        short,
+        long,
+        default_value = "https://github.com/NixOS/nixpkgs.git",
+        env = "NIXPKGS_VAULT_GIT_URL"
+    )]
+    git_url: String,
+
+    /// Number of parallel threads (0 = auto-detect)
+    #[arg(short = 'j', long, default_value = "0", env = "NIXPKGS_VAULT_THREADS")]
+    threads: usize,
+
+    /// Maximum number of concurrent nix evaluations (default: the thread count)
+    #[arg(long, value_name = "N", env = "NIXPKGS_VAULT_MAX_EVAL_JOBS")]
+    max_eval_jobs: Option<usize>,
+
+    /// Limit number of packages to process (0 = no limit)
+    #[arg(short, long, default_value = "0")]
+    limit: usize,
+
+    /// Skip this many packages of the name-sorted list before applying --limit
+    #[arg(long, value_name = "N", default_value = "0")]
+    skip: usize,
+
+    /// Limit number of packages per top-level attribute prefix (0 = no limit)
+    #[arg(long, default_value = "0")]
+    limit_per_prefix: usize,
+
+    /// Packages evaluated per `nix derivation show` call (1 = one call per package)
+    #[arg(long, default_value = "50")]
+    batch_size: usize,
+
+    /// Skip packages listed as broken on this platform in meta.badPlatforms (repeatable)
+    #[arg(long, value_name = "PLATFORM")]
+    exclude_bad_platform: Vec<String>,
+
+    /// Only process packages with this license, by short name or SPDX id, ignoring case (repeatable)
+    #[arg(long, value_name = "LICENSE", visible_alias = "license")]
+    filter_license: Vec<String>,
+
+    /// Skip packages with an unfree license
+    #[arg(long)]
+    exclude_unfree: bool,
+
+    /// Only process packages whose meta.platforms lists this platform
+    #[arg(long, value_name = "PLATFORM", visible_alias = "platform")]
+    filter_platform: Option<String>,
+
+    /// Evaluate packages for this system instead of the host, e.g. aarch64-darwin
+    #[arg(
+        long,
+        value_name = "SYSTEM",
+        value_parser = parse_system,
+        env = "NIXPKGS_VAULT_SYSTEM"
+    )]
+    system: Option<String>,
+
+    /// Skip packages marked as broken
+    #[arg(long, visible_alias = "skip-broken")]
+    exclude_broken: bool,
+
+    /// Skip packages that meta.available marks as unavailable on this system
+    #[arg(long)]
+    skip_unavailable: bool,
+
+    /// Only process packages this GitHub user maintains (repeatable)
+    #[arg(long, value_name = "HANDLE")]
+    maintainer: Vec<String>,
+
+    /// Only process attributes matching this glob, e.g. 'python3Packages.*' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip attributes matching this glob, e.g. '*-unwrapped' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Fail instead of prompting when the output directory is not empty
+    #[arg(long)]
+    no_overwrite: bool,
+
+    /// Overwrite an existing vault without prompting
+    #[arg(
+        short = 'f',
+        long,
+        visible_alias = "yes",
+        conflicts_with = "no_overwrite"
+    )]
+    force: bool,
+
+    /// Render an arbitrary packages.json value in each note, e.g.
+    /// `/meta/mainProgram=Main program` (repeatable)
+    #[arg(long, value_name = "POINTER=LABEL", value_parser = parse_extract)]
+    extract: Vec<Extract>,
+
+    /// JSON file mapping attribute names or prefixes ending in `.` to
+    /// replacements, e.g. `{"python311Packages.": "py."}`
+    #[arg(long, value_name = "FILE")]
+    rename_map: Option<String>,
+
+    /// Format of the generated package notes
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown, env = "NIXPKGS_VAULT_FORMAT")]
+    format: OutputFormat,
+
+    /// Markdown and HTML notes from this template instead of the built-in layout
+    #[arg(long, value_name = "FILE", conflicts_with = "refresh_metadata")]
+    template: Option<String>,
+
+    /// How package notes are arranged below packages/
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Layout::Flat,
+        env = "NIXPKGS_VAULT_LAYOUT",
+        visible_alias = "naming"
+    )]
+    layout: Layout,
+
+    /// File extension for package notes (default: derived from --format)
+    #[arg(long)]
+    notes_extension: Option<String>,
+
+    /// Write the transitive dependency graph of this package to graphs/ (repeatable)
+    #[arg(long, value_name = "PACKAGE")]
+    graph: Vec<String>,
+
+    /// Collapse this dependency into a leaf in --graph output (repeatable)
+    #[arg(long, value_name = "PACKAGE")]
+    exclude_deps_of: Vec<String>,
+
+    /// Format of the dependency graphs written by --graph
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    graph_format: GraphFormat,
+
+    /// Write an Obsidian canvas of this package with its direct dependencies and
+    /// dependents, or of every package in this package set, to canvases/ (repeatable)
+    #[arg(long, value_name = "PACKAGE")]
+    canvas: Vec<String>,
+
+    /// Print the notes created or changed by this run, one relative path per line
+    #[arg(long)]
+    only_changed_notes_to_stdout: bool,
+
+    /// Finished packages queued for the writer of single-file outputs, which
+    /// puts them in input order; workers wait while the queue is full
+    #[arg(long, default_value = "1024")]
+    ordered_buffer: usize,
+
+    /// Truncate long descriptions to about this many characters (0 = no limit)
+    #[arg(long, value_name = "N", default_value = "0")]
+    max_description_length: usize,
+
+    /// Keep the full text of truncated descriptions in a collapsed callout
+    #[arg(long, requires = "max_description_length")]
+    keep_full_description: bool,
+
+    /// Omit maintainer email addresses from notes and JSON output
+    #[arg(long, alias = "redact")]
+    redact_emails: bool,
+
+    /// Tag notes whose derivation is missing from the local store
+    #[arg(long)]
+    validate_drv_paths: bool,
+
+    /// Record the closure size of every output already in the local store
+    #[arg(long)]
+    output_sizes: bool,
+
+    /// Write the dependency graph of all processed packages as a JSON adjacency list
+    #[arg(long, value_name = "PATH")]
+    graph_json: Option<String>,
+
+    /// Write the dependency graph of all processed packages as Graphviz DOT
+    #[arg(long, value_name = "PATH")]
+    dot_output: Option<String>,
+
+    /// List the processed packages depending on each package in its note, which
+    /// holds every note in memory until all packages are processed
+    #[arg(long)]
+    reverse_deps: bool,
+
+    /// Evaluate `src` of every package to record upstream urls and hashes
+    #[arg(long)]
+    sources: bool,
+
+    /// Stream every package to this file as NDJSON, after a versioned header line
+    #[arg(long, value_name = "PATH", visible_alias = "json-out")]
+    ndjson: Option<String>,
+
+    /// Store the computed packages.json gzip-compressed as packages.json.gz
+    #[arg(long)]
+    compress_packages_json: bool,
+
+    /// Print the time spent in each phase and add it to summary.json
+    #[arg(long)]
+    profile: bool,
+
+    /// Also process packages that failed to evaluate in the last run
+    #[arg(long)]
+    retry_failed: bool,
+
+    /// Evaluate every package even if .cache/ has its derivation for this nixpkgs
+    #[arg(long)]
+    no_cache: bool,
+
+    /// After generating, remove notes of packages no longer in packages.json and of replaced derivations
+    #[arg(long, conflicts_with = "dry_run")]
+    prune: bool,
+
+    /// Move pruned notes to archive/ instead of deleting them
+    #[arg(long, requires = "prune")]
+    archive: bool,
+
+    /// Remove the cached derivations of every nixpkgs before generating
+    #[arg(long, conflicts_with = "dry_run")]
+    clear_cache: bool,
+
+    /// Don't fetch or evaluate nixpkgs, render notes from packages.json and .cache/ of the last run
+    #[arg(long, conflicts_with_all = ["no_cache", "clear_cache", "sources"])]
+    offline: bool,
+
+    /// Seconds to wait for each nix call of a package, e.g. `nix derivation show`, before killing it (0 = no limit)
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "30",
+        env = "NIXPKGS_VAULT_EVAL_TIMEOUT"
+    )]
+    eval_timeout: u64,
+
+    /// Extra attempts for evaluations that failed or timed out, with exponential backoff and jitter
+    #[arg(long, default_value = "1", env = "NIXPKGS_VAULT_RETRIES")]
+    retries: usize,
+
+    /// Run this command with the note path appended after each note is written
+    #[arg(long, value_name = "COMMAND")]
+    post_note_hook: Option<String>,
+
+    /// Check evaluated source hashes against the outputHash of their derivations
+    #[arg(long, requires = "sources")]
+    include_source_hash: bool,
+
+    /// Rewrite the metadata of existing notes from packages.json without evaluating
+    #[arg(long)]
+    refresh_metadata: bool,
+
+    /// List the notes that would be written and which exist already, without evaluating or writing anything
+    #[arg(long, conflicts_with = "refresh_metadata")]
+    dry_run: bool,
+
+    /// Add name, version, license and other metadata as front matter properties
+    #[arg(long)]
+    frontmatter: bool,
+
+    /// Don't write index.md linking every note
+    #[arg(long)]
+    no_index: bool,
+
+    /// Write SUMMARY.md, a chapter per package set and book.toml for mdBook, and
+    /// notes with standard links instead of wikilinks
+    #[arg(long)]
+    mdbook: bool,
+
+    /// Front matter of markdown notes; hugo and jekyll also get relative links
+    /// instead of wikilinks
+    #[arg(long, value_enum, default_value_t = FrontmatterStyle::Obsidian, conflicts_with = "mdbook")]
+    frontmatter_style: FrontmatterStyle,
+
+    /// Also store every package in an embedded redb database, indexed by license and maintainer
+    #[cfg(feature = "db")]
+    #[arg(long, value_name = "PATH")]
+    db: Option<String>,
+
+    /// Also write every package to a normalized SQLite database, e.g. vault.db
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH", visible_alias = "also-sqlite")]
+    sqlite: Option<String>,
+
+    /// Remove the files listed in the previous run's manifest.json before generating
+    #[arg(long, conflicts_with = "no_overwrite")]
+    clean: bool,
+
+    /// Continue an interrupted run, skipping packages it wrote a note for or saw fail
+    #[arg(long, conflicts_with_all = ["no_overwrite", "clean"])]
+    resume: bool,
+
+    /// Write the raw `nix derivation show` output next to each note
+    #[arg(long)]
+    save_drv_json: bool,
+
+    /// Pretty-print every JSON output (default: only the run summary)
+    #[arg(long, conflicts_with = "compact")]
+    pretty: bool,
+
+    /// Write every JSON output on a single line
+    #[arg(long)]
+    compact: bool,
+
+    /// Log commands and timings to nixpkgs-vault.log (-vv for everything)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Hide the progress bar and per-package messages, and log only warnings
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// How to report progress while processing packages
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Bar)]
+    progress_format: ProgressFormat,
+
+    /// Write JSON progress events to this file or named pipe instead of stdout
+    #[arg(long, value_name = "PATH")]
+    progress_output: Option<String>,
+}
+
+/// A `--extract` request: JSON pointer into the package's packages.json
+/// entry and the label it is rendered under.
+#[derive(Clone, Debug)]
+struct Extract {
+    pointer: String,
+    label: String,
+}
+
+fn parse_extract(value: &str) -> Result<Extract, String> {
+    let (pointer, label) = value
+        .rsplit_once('=')
+        .ok_or("expected <json-pointer>=<label>")?;
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return Err(format!("JSON pointer must start with '/': {}", pointer));
+    }
+    if label.trim().is_empty() {
+        return Err("label must not be empty".to_string());
+    }
+    Ok(Extract {
+        pointer: pointer.to_string(),
+        label: label.trim().to_string(),
+    })
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Generate the vault, as when no command is given
+    Generate,
+    /// Rewrite the metadata of existing notes from packages.json without
+    /// evaluating, as `--refresh-metadata` does
+    Update,
+    /// Print the counts and failures of the last run in `--outdir`
+    Stats,
+    /// Find the notes in `--outdir` of packages whose attribute path matches
+    Query(query::QueryArgs),
+    /// Print a table of the packages in `--outdir`, e.g. `nixpkgs-vault export csv > packages.csv`
+    Export(export::ExportArgs),
+    /// Print the dependency graph of the packages in `--outdir`, e.g. `nixpkgs-vault graph --root hello --depth 2 | dot -Tsvg`
+    Graph(graph::GraphArgs),
+    /// Remove the files the last run in `--outdir` generated, or with
+    /// `--stale` the notes of packages gone from packages.json
+    Clean(prune::CleanArgs),
+    /// Print shell completions, e.g. `nixpkgs-vault completions bash > /etc/bash_completion.d/nixpkgs-vault`
+    Completions {
+        /// Shell to complete in
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the man page, e.g. `nixpkgs-vault man > nixpkgs-vault.1`
+    Man {
+        /// Write nixpkgs-vault.1 and a page for every subcommand into this directory instead
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<String>,
+    },
+    /// Time fetching, enumeration and processing of a fixed package sample
+    Bench(bench::BenchArgs),
+    /// Browse the generated vault in `--outdir` over HTTP
+    #[cfg(feature = "serve")]
+    Serve(serve::ServeArgs),
+}
+
+impl Args {
+    /// Whether JSON should be pretty-printed, falling back to `default`
+    /// when neither `--pretty` nor `--compact` was given.
+    fn pretty_json(&self, default: bool) -> bool {
+        if self.pretty {
+            true
+        } else if self.compact {
+            false
+        } else {
+            default
+        }
+    }
+
+    /// Extension markdown notes spell their wikilinks with. HTML pages are
+    /// rendered from markdown whose links carry no extension.
+    fn link_extension(&self) -> &str {
+        match self.format {
+            OutputFormat::Html => "md",
+            _ => self.notes_extension(),
+        }
+    }
+
+    /// Folder of the output directory package notes go to.
+    fn notes_dir(&self) -> &'static str {
+        match self.format {
+            OutputFormat::Logseq => "pages",
+            _ => "packages",
+        }
+    }
+
+    /// Extension used for package notes, without the leading dot.
+    fn notes_extension(&self) -> &str {
+        self.notes_extension
+            .as_deref()
+            .map(|ext| ext.trim_start_matches('.'))
+            .unwrap_or_else(|| self.format.extension())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Obsidian-flavoured markdown notes
+    Markdown,
+    /// One JSON document per package
+    Json,
+    /// A single printable Typst catalog with one page per package
+    Typst,
+    /// Standalone HTML pages linked to each other, plus a searchable index.html
+    Html,
+    /// Logseq pages under pages/, with `property:: value` pairs and outline blocks
+    Logseq,
+    /// Org files with `#+TITLE`, a property drawer and org links between packages
+    Org,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "md",
+            OutputFormat::Json => "json",
+            OutputFormat::Typst => "typ",
+            OutputFormat::Html => "html",
+            OutputFormat::Logseq => "md",
+            OutputFormat::Org => "org",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Layout {
+    /// One note per derivation, named after its store path
+    Flat,
+    /// Nested directories mirroring the attribute path, e.g.
+    /// packages/python3Packages/requests.md
+    AttrTree,
+    /// One note per package named after its dot-separated attribute path,
+    /// e.g. packages/python3Packages.requests.md, the hierarchy Dendron reads
+    Dendron,
+    /// Dendron names prefixed with a 12-digit ID derived from the attribute
+    /// path, e.g. packages/952948746399-python3Packages.requests.md, kept in
+    /// zettel-ids.json
+    Zettel,
+}
+
+/// A markdown note held back until every note's path is known, so its
+/// dependency links can point at attribute paths instead of store paths, or
+/// until every package's dependencies are known for `--reverse-deps`.
+struct DeferredNote {
+    package: String,
+    title: String,
+    drv_path: String,
+    relative_path: String,
+    content: String,
+}
+
+/// Counts of a finished run, also written to `summary.json`.
+#[derive(Serialize)]
+pub struct RunSummary {
+    revision: String,
+    git_url: String,
+    nixpkgs_path: String, // store path of the fetched nixpkgs, reused by --offline
+    generated_at: String,
+    total: usize,
+    matched: usize, // left after --exclude-bad-platform and the --filter-* flags
+    processed: usize,
+    errors: usize,
+    failed_packages: Vec<String>, // sorted, details in errors.json
+    known_failing: usize,         // skipped, see failed-cache.json
+    resumed: usize,               // skipped with --resume, noted by an earlier run
+    hook_failures: usize,         // --post-note-hook exits other than success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<ProfileReport>, // with --profile
+}
+
+impl RunSummary {
+    /// Packages in `packages.json`.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Packages processed after filters, `--skip` and `--limit`.
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    /// Packages that failed to evaluate or to save, sorted by name.
+    pub fn failed_packages(&self) -> &[String] {
+        &self.failed_packages
+    }
+}
+
+/// One entry of `failures.json`; `errors.json` has just the package and
+/// the error.
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct PackageError {
+    package: String,
+    error: String,
+    kind: FailureKind,
+    stderr: Option<String>, // what nix printed, or the parse or spawn error
+}
+
+/// One entry of `errors.json`.
+#[derive(Serialize)]
+struct ErrorSummary<'a> {
+    package: &'a str,
+    error: &'a str,
+}
+
+impl<'a> From<&'a PackageError> for ErrorSummary<'a> {
+    fn from(e: &'a PackageError) -> Self {
+        ErrorSummary {
+            package: &e.package,
+            error: &e.error,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PackageInfo {
+    name: String,      // display name, after --rename-map
+    attribute: String, // attribute path in packages.json
+    version: String,
+    available: bool,
+    broken: bool,
+    broken_reason: Option<String>,
+    description: Option<String>,
+    homepage: Option<String>,
+    download_page: Option<String>,
+    repositories: BTreeMap<String, String>, // meta.repositories, e.g. git -> url
+    license_short_name: String,
+    long_description: Option<String>,
+    long_description_truncated: bool, // cut to --max-description-length
+    #[serde(skip)]
+    full_long_description: Option<String>, // with --keep-full-description
+    maintainers: Vec<Maintainer>,
+    teams: Vec<Team>,
+    position: Option<String>,                    // nix source position
+    drv_path: String,                            // comes from evaluation
+    drv_missing: bool,    // drv_path not in the store, with --validate-drv-paths
+    outputs: Vec<String>, // comes from drv file
+    outputs_to_install: Vec<String>, // meta.outputsToInstall, else the first output
+    output_paths: BTreeMap<String, String>, // output name -> store path, comes from drv file
+    output_sizes: Option<BTreeMap<String, u64>>, // output name -> closure size, with --output-sizes
+    input_srcs: Vec<String>, // comes from drv file
+    input_drvs: Vec<String>, // comes from drv file
+    priority: Option<i64>, // meta.priority, lower wins file collisions
+    platforms: Vec<String>,
+    bad_platforms: Vec<String>,
+    dependencies: Vec<String>, // List of dependencies' store paths, comes from the drv file
+    extracted: Vec<ExtractedField>, // values requested with --extract
+    sources: Vec<Source>,      // `src`/`srcs`, urls and hashes with --sources
+    #[serde(skip)]
+    raw_derivation: String, // unprocessed `nix derivation show` output
+    #[serde(skip)]
+    zettel_id: Option<String>, // with --naming zettel
+}
+
+#[derive(Serialize)]
+struct Source {
+    store_path: Option<String>,
+    urls: Vec<String>,
+    hash: Option<String>,
+    rev: Option<String>,             // git and other VCS fetchers
+    derivation_hash: Option<String>, // outputHash of the producing drv, with --include-source-hash
+}
+
+impl Source {
+    fn from_store_path(path: &str) -> Self {
+        Source {
+            store_path: Some(path.to_string()),
+            urls: Vec::new(),
+            hash: None,
+            rev: None,
+            derivation_hash: None,
+        }
+    }
+
+    /// The evaluated hash disagrees with the fixed-output derivation that
+    /// produces this source.
+    fn hash_mismatch(&self) -> bool {
+        matches!(
+            (&self.hash, &self.derivation_hash),
+            (Some(hash), Some(derivation_hash)) if hash != derivation_hash
+        )
+    }
+
+    /// Parses one entry of the `SOURCES_EXPR` evaluation result.
+    fn from_json(value: &Value) -> Self {
+        Source {
+            store_path: value["path"].as_str().map(|s| s.to_string()),
+            urls: string_list(&value["urls"]),
+            hash: value["hash"]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string()),
+            rev: value["rev"].as_str().map(|s| s.to_string()),
+            derivation_hash: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExtractedField {
+    label: String,
+    value: Value,
+}
+
+#[derive(Serialize)]
+struct Maintainer {
+    name: Option<String>,
+    email: Option<String>,
+    github: Option<String>,
+}
+
+impl Maintainer {
+    /// Parses a maintainer entry, either the attribute set form or the
+    /// legacy `"Name <email>"` string form.
+    fn from_json(entry: &Value) -> Option<Maintainer> {
+        let field = |key: &str| {
+            entry
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+        match entry {
+            Value::Object(_) => Some(Maintainer {
+                name: field("name"),
+                email: field("email"),
+                github: field("github"),
+            }),
+            Value::String(s) => {
+                let (name, email) = match s.split_once('<') {
+                    Some((name, rest)) if rest.ends_with('>') => (
+                        name.trim().to_string(),
+                        Some(rest.trim_end_matches('>').trim().to_string()),
+                    ),
+                    _ => (s.trim().to_string(), None),
+                };
+                Some(Maintainer {
+                    name: Some(name),
+                    email,
+                    github: None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Identifier used for tags and deduplication, preferring the GitHub
+    /// username over the display name.
+    fn handle(&self) -> String {
+        self.github
+            .as_deref()
+            .or(self.name.as_deref())
+            .or(self.email.as_deref())
+            .unwrap_or("unknown")
+            .to_string()
+    }
+
+    fn display(&self) -> String {
+        match (&self.github, &self.name) {
+            (Some(github), _) => format!("@{}", github),
+            (None, Some(name)) => name.clone(),
+            (None, None) => self.handle(),
+        }
+    }
+
+    /// Markdown list entry, e.g. `Alice ([@alice](https://github.com/alice))`.
+    fn markdown(&self) -> String {
+        let github = self
+            .github
+            .as_ref()
+            .map(|github| format!("[@{0}](https://github.com/{0})", github));
+        let details: Vec<String> = github.iter().chain(&self.email).cloned().collect();
+        match (&self.name, details.is_empty()) {
+            (Some(name), true) => name.clone(),
+            (Some(name), false) => format!("{} ({})", name, details.join(", ")),
+            (None, false) => details.join(", "),
+            (None, true) => self.handle(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Team {
+    short_name: String,
+    members: Vec<Maintainer>,
+}
+
+/// Generates the vault described by `args`, or runs its subcommand.
+/// Returns the summary of the run, or `None` when a subcommand other than
+/// `generate`, `--refresh-metadata` or `--dry-run` ran instead of a regular
+/// generation.
+pub fn run(args: Args) -> Result<Option<RunSummary>, VaultError> {
+    // Nothing else may end up in the completion script
+    if let Some(Commands::Completions { shell }) = args.command {
+        // Buffered, as clap_complete panics on write errors like a closed pipe
+        let mut script = Vec::new();
+        clap_complete::generate(
+            shell,
+            &mut Args::command(),
+            env!("CARGO_PKG_NAME"),
+            &mut script,
+        );
+        std::io::stdout()
+            .write_all(&script)
+            .map_err(|e| VaultError::io("failed to write completions", e))?;
+        return Ok(None);
+    }
+    if let Some(Commands::Man { out_dir }) = &args.command {
+        write_man_pages(out_dir.as_deref())?;
+        return Ok(None);
+    }
+    if let Some(Commands::Export(export_args)) = &args.command {
+        export::run(export_args, &args)?;
+        return Ok(None);
+    }
+    if let Some(Commands::Graph(graph_args)) = &args.command {
+        graph::run(graph_args, &args)?;
+        return Ok(None);
+    }
+    if matches!(args.format, OutputFormat::Logseq | OutputFormat::Org)
+        && args.layout != Layout::Flat
+    {
+        // Logseq keeps all pages in one folder and reads namespaces from the
+        // file name; org links are relative to the linking file. Neither
+        // gets its links rewritten to attribute paths
+        return Err(anyhow!(
+            "--format {} can't be combined with --layout {}",
+            args.format.to_possible_value().unwrap().get_name(),
+            args.layout.to_possible_value().unwrap().get_name()
+        )
+        .into());
+    }
+    if args.mdbook && args.format != OutputFormat::Markdown {
+        return Err(anyhow!("--mdbook needs --format markdown").into());
+    }
+    if args.frontmatter_style != FrontmatterStyle::Obsidian && args.format != OutputFormat::Markdown
+    {
+        return Err(anyhow!("--frontmatter-style needs --format markdown").into());
+    }
+
+    // Configure rayon thread pool
+    let num_threads = if args.threads == 0 {
+        num_cpus::get()
+    } else {
+        args.threads
+    };
+
+    // The global pool can only be built once per process; a program calling
+    // `run` again keeps the pool of its first call
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+    {
+        tracing::debug!(error = %e, "keeping the existing thread pool");
+    }
+
+    println!(
+        "{} {}",
+        "🚀 Using threads:".cyan().bold(),
+        num_threads.to_string().bright_white()
+    );
+
+    match &args.command {
+        Some(Commands::Bench(bench_args)) => {
+            bench::run(bench_args, &args, num_threads)?;
+            return Ok(None);
+        }
+        #[cfg(feature = "serve")]
+        Some(Commands::Serve(serve_args)) => {
+            serve::run(serve_args, &args)?;
+            return Ok(None);
+        }
+        Some(Commands::Stats) => {
+            stats::run(&args)?;
+            return Ok(None);
+        }
+        Some(Commands::Query(query_args)) => {
+            query::run(query_args, &args)?;
+            return Ok(None);
+        }
+        Some(Commands::Clean(clean_args)) if clean_args.stale() => {
+            let packages_json_path = find_packages_json(&args.outdir).ok_or_else(|| {
+                anyhow!(
+                    "{} has no packages.json, generate a vault first",
+                    args.outdir
+                )
+            })?;
+            let parsed_json = load_packages_json(&packages_json_path)?;
+            let packages = parsed_json["packages"]
+                .as_object()
+                .ok_or_else(|| anyhow!("{} has no \"packages\" object", packages_json_path))?;
+            let rename_map = match args.rename_map {
+                Some(ref path) => load_rename_map(path)?,
+                None => Vec::new(),
+            };
+            let notes = note_index::load(&args.outdir).notes;
+            let pruned = prune_stale_notes(
+                &args,
+                packages,
+                &rename_map,
+                &[&notes],
+                clean_args.archive(),
+            )
+            .context("failed to prune stale notes")?;
+            print_pruned(&pruned, clean_args.archive());
+            return Ok(None);
+        }
+        Some(Commands::Clean(_)) => {
+            clean_previous_run(&args.outdir);
+            if let Err(e) = fs::remove_file(manifest::path(&args.outdir)) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(VaultError::io("failed to remove manifest.json", e));
+                }
+            }
+            return Ok(None);
+        }
+        Some(Commands::Update) if args.dry_run || args.template.is_some() => {
+            return Err(anyhow!("update can't be combined with --dry-run or --template").into());
+        }
+        Some(Commands::Completions { .. })
+        | Some(Commands::Man { .. })
+        | Some(Commands::Export(_))
+        | Some(Commands::Graph(_)) => {
+            unreachable!("completions, man pages, exports and graphs are printed first")
+        }
+        Some(Commands::Generate) | Some(Commands::Update) | None => {}
+    }
+    let refresh_metadata = args.refresh_metadata || matches!(args.command, Some(Commands::Update));
+
+    // Read before --clean removes summary.json
+    let offline_nixpkgs = args
+        .offline
+        .then(|| previous_nixpkgs_path(&args.outdir))
+        .transpose()?;
+
+    // A dry run leaves the output directory alone
+    if !args.dry_run {
+        prepare_outdir(&args)?;
+        logging::init(&args.outdir, logging::level(args.verbose, args.quiet))?;
+    }
+    tracing::info!(revision = %args.revision, git_url = %args.git_url, "starting run");
+
+    let mut profiler = Profiler::default();
+    let nixpkgs_path = match offline_nixpkgs {
+        Some(nixpkgs_path) => {
+            println!(
+                "{} {}",
+                "📴 Offline, reusing the evaluations of:".cyan().bold(),
+                nixpkgs_path.bright_white()
+            );
+            nixpkgs_path
+        }
+        None => {
+            println!(
+                "{} {}",
+                "📦 Fetching nixpkgs from:".cyan().bold(),
+                format!(
+                    "{}/tree/{}",
+                    args.git_url.trim_end_matches(".git"),
+                    args.revision
+                )
+                .blue()
+                .underline()
+            );
+
+            let start = Instant::now();
+            let nixpkgs_path = fetch_nixpkgs_with_nix(&args.git_url, &args.revision)?;
+            profiler.phase("fetch", start);
+
+            println!(
+                "{} {}",
+                "✅ Nixpkgs fetched to:".green().bold(),
+                nixpkgs_path.bright_white()
+            );
+
+            analyze_nixpkgs(&nixpkgs_path)?;
+            nixpkgs_path
+        }
+    };
+    let derivation_cache = (!args.no_cache)
+        .then(|| DerivationCache::new(&args.outdir, &nixpkgs_path, args.system.as_deref()));
+
+    // A dry run computes a missing packages.json in a scratch directory
+    let packages_json_dir = match find_packages_json(&args.outdir) {
+        None if args.dry_run => std::env::temp_dir()
+            .join(format!("nixpkgs-vault-dry-run-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned(),
+        _ => args.outdir.clone(),
+    };
+    let packages_json_path = match find_packages_json(&packages_json_dir) {
+        Some(path) => path,
+        None if args.compress_packages_json => format!("{}/packages.json.gz", packages_json_dir),
+        None => format!("{}/packages.json", packages_json_dir),
+    };
+    if Path::new(&packages_json_path).exists() {
+        println!(
+            "{} {}",
+            "⚠️  packages.json already exists in:".yellow().bold(),
+            packages_json_path.bright_white()
+        );
+        println!("{}", "⚠️  Skipping computation.".yellow().bold());
+    } else if args.offline {
+        return Err(anyhow!(
+            "--offline needs the packages.json of an earlier run in {}",
+            args.outdir
+        )
+        .into());
+    } else {
+        // create outdir if not exists
+        std::fs::create_dir_all(&packages_json_dir)
+            .with_context(|| format!("failed to create output directory {}", packages_json_dir))?;
+        let start = Instant::now();
+        generate_packages_json(&nixpkgs_path, args.system.as_deref(), &packages_json_dir)?;
+        if args.compress_packages_json {
+            compress_packages_json(&packages_json_dir)?;
+        }
+        profiler.phase("enumerate", start);
+    }
+
+    // print loading packages.json
+    println!(
+        "{} {}",
+        "📥 Loading packages.json to memory:".cyan().bold(),
+        packages_json_path.bright_white()
+    );
+    let start = Instant::now();
+    let parsed_json = load_packages_json(&packages_json_path)?;
+    profiler.phase("load", start);
+    let packages = parsed_json["packages"].as_object().ok_or_else(|| {
+        anyhow!(
+            "packages.json at {} has no \"packages\" object",
+            packages_json_path
+        )
+    })?;
+
+    println!(
+        "{} {}",
+        "📊 Total packages found:".cyan().bold(),
+        packages.len().to_string().bright_white()
+    );
+
+    let rename_map = match args.rename_map {
+        Some(ref path) => load_rename_map(path)?,
+        None => Vec::new(),
+    };
+
+    let template = match args.template {
+        Some(ref path) => Some(NoteTemplate::load(path)?),
+        None => None,
+    };
+
+    if refresh_metadata {
+        refresh::run(packages, &rename_map, &args)?;
+        return Ok(None);
+    }
+
+    // Process packages in parallel
+    println!("{}", "📦 Processing packages:".cyan().bold());
+
+    // Sorted by attribute name, whatever order the JSON map keeps, so
+    // `--skip 5000 --limit 100` is a reproducible range
+    let mut packages_vec: Vec<_> = packages.iter().collect();
+    packages_vec.sort_unstable_by_key(|(name, _)| *name);
+
+    // Drop packages known not to work on the excluded platforms
+    if !args.exclude_bad_platform.is_empty() {
+        packages_vec.retain(|(_, info)| {
+            let bad_platforms = string_list(&info["meta"]["badPlatforms"]);
+            !args
+                .exclude_bad_platform
+                .iter()
+                .any(|platform| bad_platforms.contains(platform))
+        });
+        println!(
+            "{} {}",
+            "🔎 Packages left after excluding bad platforms:"
+                .yellow()
+                .bold(),
+            packages_vec.len().to_string().bright_white()
+        );
+    }
+
+    // Filters combine: a package has to pass all of them
+    let filtering = !args.filter_license.is_empty()
+        || args.exclude_unfree
+        || args.filter_platform.is_some()
+        || args.exclude_broken
+        || args.skip_unavailable
+        || !args.maintainer.is_empty()
+        || !args.include.is_empty()
+        || !args.exclude.is_empty();
+    if filtering {
+        packages_vec.retain(|(name, info)| {
+            let included = args.include.is_empty()
+                || args.include.iter().any(|pattern| glob_match(pattern, name));
+            let excluded = args.exclude.iter().any(|pattern| glob_match(pattern, name));
+            let meta = &info["meta"];
+            let licenses = license_names(&meta["license"]);
+            let license_matches = args.filter_license.is_empty()
+                || args.filter_license.iter().any(|wanted| {
+                    licenses
+                        .iter()
+                        .any(|license| license.eq_ignore_ascii_case(wanted))
+                });
+            let unfree = is_unfree(&meta["license"]);
+            let platform_matches = args
+                .filter_platform
+                .as_ref()
+                .is_none_or(|platform| string_list(&meta["platforms"]).contains(platform));
+            let maintained = args.maintainer.is_empty()
+                || meta["maintainers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Maintainer::from_json)
+                    .any(|maintainer| {
+                        args.maintainer.iter().any(|handle| {
+                            maintainer
+                                .handle()
+                                .eq_ignore_ascii_case(handle.trim_start_matches('@'))
+                        })
+                    });
+            let broken = match &meta["broken"] {
+                Value::Bool(broken) => *broken,
+                Value::String(_) => true,
+                _ => false,
+            };
+            included
+                && !excluded
+                && maintained
+                && license_matches
+                && !(args.exclude_unfree && unfree)
+                && platform_matches
+                && !(args.exclude_broken && broken)
+                && !(args.skip_unavailable && meta["available"] == Value::Bool(false))
+        });
+        println!(
+            "{} {}",
+            "🔎 Packages matching filters:".yellow().bold(),
+            packages_vec.len().to_string().bright_white()
+        );
+    }
+    let matched = packages_vec.len();
+
+    // Sample evenly across attribute namespaces if requested
+    if args.limit_per_prefix > 0 {
+        let mut per_prefix: HashMap<&str, usize> = HashMap::new();
+        packages_vec.retain(|(name, _)| {
+            let count = per_prefix.entry(attribute_prefix(name)).or_insert(0);
+            *count += 1;
+            *count <= args.limit_per_prefix
+        });
+        println!(
+            "{} {} {} {}",
+            "🔢 Limited per prefix to packages:".yellow().bold(),
+            args.limit_per_prefix.to_string().bright_white(),
+            "across prefixes:".yellow().bold(),
+            per_prefix.len().to_string().bright_white()
+        );
+    }
+
+    // A range of the sorted list, see above
+    if args.skip > 0 {
+        packages_vec.drain(..args.skip.min(packages_vec.len()));
+        println!(
+            "{} {}",
+            "⏭️  Skipped packages:".yellow().bold(),
+            args.skip.to_string().bright_white()
+        );
+    }
+
+    // Packages that failed against the same nixpkgs will fail again
+    let mut failed_cache = FailedCache::load(&args.outdir, &args.revision, &nixpkgs_path);
+    let checkpoint = if args.resume {
+        note_index::load(&args.outdir)
+    } else {
+        note_index::Checkpoint::default()
+    };
+    // The interrupted run never got to update failed-cache.json
+    for (package, error) in &checkpoint.failures {
+        if error == EVALUATION_FAILED || error == DERIVATION_NOT_FOUND {
+            failed_cache.insert(package.clone(), error.clone());
+        }
+    }
+    let mut known_failing = 0;
+    if !args.retry_failed && !failed_cache.is_empty() {
+        let before = packages_vec.len();
+        packages_vec.retain(|(name, _)| !failed_cache.contains(name));
+        known_failing = before - packages_vec.len();
+        println!(
+            "{} {} {}",
+            "⏭️  Known-failing packages skipped:".yellow().bold(),
+            known_failing.to_string().bright_white(),
+            "(use --retry-failed to process them)".bright_black()
+        );
+    }
+
+    // Notes are named after the drv path, so they are looked up by attribute
+    // in the index of the interrupted run rather than by file name
+    let mut resumed_notes = Vec::new();
+    let mut resumed_packages = Vec::new();
+    if args.resume {
+        packages_vec.retain(|(name, _)| match checkpoint.notes.get(name.as_str()) {
+            Some(note) => {
+                resumed_notes.push(note.clone());
+                resumed_packages.push(name.as_str());
+                false
+            }
+            None => true,
+        });
+        println!(
+            "{} {}",
+            "⏯️  Packages already noted, resuming after:"
+                .yellow()
+                .bold(),
+            resumed_notes.len().to_string().bright_white()
+        );
+    }
+    let resumed = resumed_notes.len();
+
+    // Apply limit if specified
+    if args.limit > 0 {
+        packages_vec.truncate(args.limit);
+        println!(
+            "{} {}",
+            "🔢 Limited to packages:".yellow().bold(),
+            args.limit.to_string().bright_white()
+        );
+    }
+
+    let sample_count = packages_vec.len();
+
+    let zettel_ids = if args.layout == Layout::Zettel && !args.dry_run {
+        let mut zettel_ids = zettel::ZettelIds::load(&args.outdir);
+        zettel_ids.assign(packages_vec.iter().map(|(name, _)| name.as_str()));
+        zettel_ids
+            .save(&args.outdir)
+            .context("failed to write zettel-ids.json")?;
+        zettel_ids
+    } else {
+        zettel::ZettelIds::default()
+    };
+
+    if args.dry_run {
+        if packages_json_dir != args.outdir {
+            let _ = fs::remove_dir_all(&packages_json_dir);
+        }
+        let overwritten = print_dry_run_plan(&packages_vec, &args, &rename_map);
+        println!(
+            "{} {} {}",
+            "🧪 Dry run, packages that would be processed into"
+                .cyan()
+                .bold(),
+            args.outdir.bright_white(),
+            sample_count.to_string().bright_white()
+        );
+        println!(
+            "{} {}",
+            "📝 Existing notes that would be overwritten:".cyan().bold(),
+            overwritten.to_string().bright_white()
+        );
+        // Plain and stable for wrapper scripts
+        println!(
+            "dry-run packages={} outdir={} overwrite={}",
+            sample_count, args.outdir, overwritten
+        );
+        return Ok(None);
+    }
+
+    // Create progress tracking; hidden, it drops per-package messages too
+    let pb = if args.quiet || args.progress_format == ProgressFormat::Json {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(sample_count as u64)
+    };
+    pb.set_length(sample_count as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+
+    let processed_count = AtomicUsize::new(0);
+    let json_progress = match args.progress_format {
+        ProgressFormat::Json => Some(
+            JsonProgress::open(args.progress_output.as_deref())
+                .context("failed to open the progress output")?,
+        ),
+        ProgressFormat::Bar => None,
+    };
+    if let Some(progress) = &json_progress {
+        progress.emit(&progress::Event::Started {
+            total: sample_count,
+            revision: &args.revision,
+            outdir: &args.outdir,
+        });
+    }
+    let error_count = AtomicUsize::new(0);
+    let typst_catalog = if args.format == OutputFormat::Typst {
+        let writer =
+            typst::create_catalog(&args.outdir).context("failed to create the Typst catalog")?;
+        Some(OrderedSink::new(writer, args.ordered_buffer, 0))
+    } else {
+        None
+    };
+    let ndjson_stream = match args.ndjson {
+        Some(ref path) => {
+            let writer = ndjson::create(
+                path,
+                &args.revision,
+                &args.git_url,
+                &Utc::now().to_rfc3339(),
+                sample_count,
+            )
+            .with_context(|| format!("failed to create NDJSON stream {}", path))?;
+            Some(OrderedSink::new(
+                writer,
+                args.ordered_buffer,
+                ndjson::FLUSH_INTERVAL,
+            ))
+        }
+        None => None,
+    };
+    #[cfg(feature = "db")]
+    let package_db = match args.db {
+        Some(ref path) => Some(
+            db::PackageDb::create(path)
+                .with_context(|| format!("failed to create database {}", path))?,
+        ),
+        None => None,
+    };
+    #[cfg(feature = "sqlite")]
+    let sqlite_export = match args.sqlite {
+        Some(ref path) => Some(
+            sqlite::SqliteExport::create(path)
+                .with_context(|| format!("failed to create SQLite database {}", path))?,
+        ),
+        None => None,
+    };
+    let write_index_note = args.format == OutputFormat::Markdown && !args.no_index;
+    // Entries of packages not processed in this run are kept from the last
+    // one; the interrupted run never got to record the resumed packages
+    let index_state = Mutex::new(if write_index_note {
+        let mut index_state = index_note::IndexState::load(&args.outdir);
+        for (package, note) in resumed_packages.iter().zip(&resumed_notes) {
+            if let Some(entry) = index_note::IndexNoteEntry::from_note(&args.outdir, note) {
+                index_state.insert(package, entry);
+            }
+        }
+        index_state
+    } else {
+        index_note::IndexState::default()
+    });
+    let generated_notes = Mutex::new(resumed_notes);
+    // Opening the index starts it over, but --prune needs to know which
+    // package the notes of earlier runs belong to
+    let previous_notes = if args.prune {
+        note_index::load(&args.outdir).notes
+    } else {
+        HashMap::new()
+    };
+    let note_index = note_index::NoteIndex::open(&args.outdir, args.resume)
+        .context("failed to open note-index.jsonl")?;
+    let dependency_graph = Mutex::new(DependencyGraph::default());
+    // Drv path -> note, for the cards of --canvas
+    let canvas_notes = Mutex::new(HashMap::new());
+    let changed_notes = Mutex::new(Vec::new());
+    let package_errors = Mutex::new(Vec::new());
+    let defer_notes = (args.layout != Layout::Flat || args.reverse_deps)
+        && matches!(args.format, OutputFormat::Markdown | OutputFormat::Html);
+    let html_index = Mutex::new(Vec::new());
+    let deferred_notes = Mutex::new(Vec::new());
+
+    let post_note_hook = match args.post_note_hook {
+        Some(ref hook) => match shlex::split(hook) {
+            Some(words) if !words.is_empty() => Some(words),
+            _ => return Err(anyhow!("invalid --post-note-hook command: {}", hook).into()),
+        },
+        None => None,
+    };
+    let hook_failures = AtomicUsize::new(0);
+
+    // Hooks run on the worker that wrote the note, so at most --threads of
+    // them run at once
+    let note_saved =
+        |package: &str, relative_path: &str, result: Result<Option<String>, std::io::Error>| {
+            match result {
+                Ok(changed) => {
+                    if let Err(e) = note_index.record(package, relative_path) {
+                        pb.println(format!(
+                            "🗂️  {} (note index write failed: {})",
+                            package.yellow(),
+                            e.to_string().bright_black()
+                        ));
+                    }
+                    let Some(note_path) = changed else {
+                        return;
+                    };
+                    if let Some(ref hook) = post_note_hook {
+                        if let Err(e) =
+                            run_post_note_hook(hook, &format!("{}/{}", args.outdir, note_path))
+                        {
+                            pb.println(format!(
+                                "🪝 {} (post-note hook failed: {})",
+                                package.yellow(),
+                                e.bright_black()
+                            ));
+                            hook_failures.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    if args.only_changed_notes_to_stdout {
+                        changed_notes.lock().unwrap().push(note_path);
+                    }
+                }
+                Err(e) => {
+                    pb.println(format!(
+                        "💾 {} (save failed: {})",
+                        package.yellow(),
+                        e.to_string().bright_black()
+                    ));
+                    tracing::warn!(package, error = %e, "saving the note failed");
+                    error_count.fetch_add(1, Ordering::Relaxed);
+                    package_errors.lock().unwrap().push(PackageError {
+                        package: package.to_string(),
+                        error: format!("save failed: {}", e),
+                        kind: FailureKind::Io,
+                        stderr: None,
+                    });
+                }
+            }
+        };
+
+    let start = Instant::now();
+    let batch_size = args.batch_size.max(1);
+    // Evaluators can take hundreds of MB each; the rest of the work is cheap
+    // and keeps using every thread
+    let eval_jobs = Semaphore::new(args.max_eval_jobs.unwrap_or(num_threads));
+    let evaluator = Evaluator {
+        args: &args,
+        nixpkgs_path: &nixpkgs_path,
+        cache: derivation_cache.as_ref(),
+        eval_jobs: &eval_jobs,
+    };
+    packages_vec
+        .par_chunks(batch_size)
+        .enumerate()
+        .for_each(|(chunk_index, chunk)| {
+            let mut chunk_evaluation = evaluator.chunk(chunk);
+
+            for (offset, (name, info)) in chunk.iter().enumerate() {
+                let index = chunk_index * batch_size + offset;
+                let mut package_info = prepare_package_info(name, info, &args, &rename_map);
+                package_info.zettel_id = zettel_ids.get(name).map(str::to_string);
+                let mut typst_page = None;
+                let mut ndjson_record = None;
+
+                let evaluation_start = Instant::now();
+                let (evaluation, from_cache) = chunk_evaluation.evaluate(name, &mut package_info);
+                profiler.record_evaluation(evaluation_start.elapsed() + chunk_evaluation.share);
+
+                if let (Some(cache), Ok(()), false) = (&derivation_cache, &evaluation, from_cache) {
+                    if let Err(e) = cache.put(name, &package_info.raw_derivation) {
+                        pb.println(format!(
+                            "🗃️  {} (derivation cache write failed: {})",
+                            name.yellow(),
+                            e.to_string().bright_black()
+                        ));
+                    }
+                }
+
+                let failure_message = evaluation.as_ref().err().map(ToString::to_string);
+                if let Err(failure) = evaluation {
+                    match failure {
+                        EvaluationFailure::Failed(_)
+                        | EvaluationFailure::NotFound(_)
+                        | EvaluationFailure::InvalidJson(_) => {
+                            pb.println(format!("❌ {}", name.red()))
+                        }
+                        EvaluationFailure::Spawn(_) | EvaluationFailure::NotCached => pb.println(
+                            format!("📴 {} ({})", name.red(), failure.to_string().bright_black()),
+                        ),
+                        EvaluationFailure::TimedOut(_) => pb.println(format!(
+                            "⏰ {} ({})",
+                            name.red(),
+                            failure.to_string().bright_black()
+                        )),
+                    }
+                    tracing::warn!(package = name.as_str(), %failure, "evaluation failed");
+                    error_count.fetch_add(1, Ordering::Relaxed);
+                    if let Err(e) = note_index.record_failure(name, &failure.to_string()) {
+                        pb.println(format!(
+                            "🗂️  {} (note index write failed: {})",
+                            name.yellow(),
+                            e.to_string().bright_black()
+                        ));
+                    }
+                    package_errors.lock().unwrap().push(PackageError {
+                        package: name.to_string(),
+                        error: failure.to_string(),
+                        kind: failure.kind(),
+                        stderr: failure.details(),
+                    });
+                } else {
+                    if args.validate_drv_paths {
+                        package_info.drv_missing = !Path::new(&package_info.drv_path).exists();
+                    }
+
+                    if args.output_sizes {
+                        measure_output_sizes(&mut package_info, &eval_jobs, args.eval_timeout);
+                    }
+
+                    if args.sources {
+                        let permit = eval_jobs.acquire();
+                        fetch_sources(
+                            name,
+                            &nixpkgs_path,
+                            args.system.as_deref(),
+                            args.eval_timeout,
+                            &mut package_info,
+                        );
+                        drop(permit);
+                        if args.include_source_hash {
+                            verify_source_hashes(&mut package_info, &eval_jobs, args.eval_timeout);
+                        }
+                    }
+
+                    if !args.canvas.is_empty() && args.format != OutputFormat::Typst {
+                        canvas_notes.lock().unwrap().insert(
+                            package_info.drv_path.clone(),
+                            note_path(&package_info, &args),
+                        );
+                    }
+                    if !args.graph.is_empty()
+                        || !args.canvas.is_empty()
+                        || args.graph_json.is_some()
+                        || args.dot_output.is_some()
+                        || args.reverse_deps
+                    {
+                        dependency_graph.lock().unwrap().insert(&package_info);
+                    }
+
+                    if args.format != OutputFormat::Typst {
+                        generated_notes
+                            .lock()
+                            .unwrap()
+                            .push(note_path(&package_info, &args));
+                    }
+
+                    if args.save_drv_json {
+                        generated_notes
+                            .lock()
+                            .unwrap()
+                            .push(format!("{}.drv.json", note_base(&package_info, &args)));
+                        if let Err(e) = save_derivation_json(&package_info, &args) {
+                            pb.println(format!(
+                                "💾 {} (derivation JSON save failed: {})",
+                                name.yellow(),
+                                e.to_string().bright_black()
+                            ));
+                        }
+                    }
+
+                    if ndjson_stream.is_some() {
+                        // PackageInfo only holds strings, maps and numbers
+                        ndjson_record = ndjson::record(&package_info).ok();
+                    }
+
+                    #[cfg(feature = "db")]
+                    if let Some(ref package_db) = package_db {
+                        if let Err(e) = package_db.insert(&package_info) {
+                            pb.println(format!(
+                                "🗃️  {} (database write failed: {})",
+                                name.yellow(),
+                                e.to_string().bright_black()
+                            ));
+                        }
+                    }
+                    #[cfg(feature = "sqlite")]
+                    if let Some(ref sqlite_export) = sqlite_export {
+                        if let Err(e) = sqlite_export.insert(&package_info) {
+                            pb.println(format!(
+                                "🗃️  {} (SQLite write failed: {})",
+                                name.yellow(),
+                                e.to_string().bright_black()
+                            ));
+                        }
+                    }
+
+                    if write_index_note {
+                        let note = note_path(&package_info, &args);
+                        index_state.lock().unwrap().insert(
+                            name,
+                            index_note::IndexNoteEntry {
+                                name: package_info.name.clone(),
+                                target: note.strip_suffix(".md").unwrap_or(&note).to_string(),
+                                broken: package_info.broken,
+                                available: package_info.available,
+                            },
+                        );
+                    }
+
+                    if args.format == OutputFormat::Html {
+                        html_index.lock().unwrap().push(html::IndexEntry {
+                            name: package_info.name.clone(),
+                            path: note_path(&package_info, &args),
+                            description: package_info.description.clone(),
+                        });
+                    }
+
+                    if args.format == OutputFormat::Typst {
+                        typst_page = Some(typst::generate_package_page(&package_info));
+                    } else if defer_notes {
+                        let relative_path = note_path(&package_info, &args);
+                        let mut content = package_note_markdown(
+                            &package_info,
+                            args.link_extension(),
+                            &args,
+                            template.as_ref(),
+                        );
+                        if args.frontmatter_style != FrontmatterStyle::Obsidian {
+                            content = site::with_front_matter(
+                                args.frontmatter_style,
+                                &package_info,
+                                &content,
+                            );
+                        }
+                        deferred_notes.lock().unwrap().push(DeferredNote {
+                            package: name.to_string(),
+                            title: package_info.name.clone(),
+                            drv_path: package_info.drv_path.clone(),
+                            relative_path,
+                            content,
+                        });
+                    } else {
+                        note_saved(
+                            name,
+                            &note_path(&package_info, &args),
+                            save_package_note(&package_info, &args, template.as_ref()),
+                        );
+                    }
+                }
+
+                // Every index has to be submitted, even for failed packages
+                if let Some(catalog) = &typst_catalog {
+                    catalog.submit(index, typst_page);
+                }
+                if let Some(stream) = &ndjson_stream {
+                    stream.submit(index, ndjson_record);
+                }
+
+                let current = processed_count.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(progress) = &json_progress {
+                    progress.emit(&match &failure_message {
+                        Some(error) => progress::Event::PackageFailed {
+                            package: name,
+                            error,
+                            done: current,
+                            total: sample_count,
+                        },
+                        None => progress::Event::PackageDone {
+                            package: name,
+                            done: current,
+                            total: sample_count,
+                        },
+                    });
+                }
+                pb.set_position(current as u64);
+                if current.is_multiple_of(10) || current < 100 {
+                    // Update message less frequently for performance
+                    pb.set_message(format!(
+                        "Processing {} ({} errors)",
+                        name,
+                        error_count.load(Ordering::Relaxed)
+                    ));
+                }
+            }
+        });
+
+    profiler.phase("process", start);
+    tracing::info!(
+        packages = sample_count,
+        errors = error_count.load(Ordering::Relaxed),
+        elapsed_secs = start.elapsed().as_secs(),
+        "processed packages"
+    );
+
+    let deferred_notes = deferred_notes.into_inner().unwrap();
+    let dependency_graph = dependency_graph.into_inner().unwrap();
+    if !deferred_notes.is_empty() {
+        let dependents = if args.reverse_deps {
+            dependency_graph.dependents()
+        } else {
+            HashMap::new()
+        };
+        let extension = format!(".{}", args.notes_extension());
+        let links: HashMap<&str, &str> = deferred_notes
+            .iter()
+            .map(|note| {
+                let target = note.relative_path.strip_suffix(&extension);
+                (
+                    drv_filename(&note.drv_path),
+                    target.unwrap_or(&note.relative_path),
+                )
+            })
+            .collect();
+        deferred_notes.par_iter().for_each(|note| {
+            let mut content = match dependents.get(note.drv_path.as_str()) {
+                Some(dependents) => insert_before_footer(
+                    &note.content,
+                    &reverse_dependencies_section(
+                        dependents,
+                        &dependency_graph,
+                        args.link_extension(),
+                    ),
+                ),
+                None => note.content.clone(),
+            };
+            if args.layout != Layout::Flat {
+                content = resolve_note_links(&content, &links, args.link_extension());
+            }
+            if args.format == OutputFormat::Html {
+                content = html::render_page(&note.title, &content, &note.relative_path);
+            }
+            if args.mdbook {
+                content =
+                    mdbook::render_page(&content, &note.relative_path, args.notes_extension());
+            }
+            if args.frontmatter_style != FrontmatterStyle::Obsidian {
+                content = site::keep_unchanged_date(
+                    site::convert_links(&content, &note.relative_path, args.notes_extension()),
+                    &format!("{}/{}", args.outdir, note.relative_path),
+                );
+            }
+            note_saved(
+                &note.package,
+                &note.relative_path,
+                write_note(&note.relative_path, &content, &args.outdir),
+            );
+        });
+    }
+
+    pb.finish_with_message(format!(
+        "All packages processed! {} total, {} errors",
+        sample_count,
+        error_count.load(Ordering::Relaxed)
+    ));
+    println!();
+    if let Some(progress) = &json_progress {
+        progress.emit(&progress::Event::Finished {
+            processed: sample_count,
+            errors: error_count.load(Ordering::Relaxed),
+            elapsed_secs: start.elapsed().as_secs_f64(),
+        });
+    }
+
+    if args.format == OutputFormat::Html {
+        match html::write_index(&args.outdir, html_index.into_inner().unwrap()) {
+            Ok(path) => println!(
+                "{} {}",
+                "✅ HTML index written to:".green().bold(),
+                path.bright_white()
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write index.html:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    if write_index_note {
+        let mut index_state = index_state.into_inner().unwrap();
+        index_state.retain(|package| packages.contains_key(package));
+        let written = index_state.write_index_note(&args.outdir).and_then(|_| {
+            save_json(
+                &index_note::path(&args.outdir),
+                &index_state,
+                args.pretty_json(false),
+            )
+        });
+        match written {
+            Ok(()) => println!(
+                "{} {}",
+                "✅ Index note written to:".green().bold(),
+                format!("{}/index.md", args.outdir).bright_white()
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write index.md:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    let mut book_files = Vec::new();
+    if args.mdbook && !args.dry_run {
+        match mdbook::write_book(&args.outdir, &note_index::load(&args.outdir).notes) {
+            Ok(files) => {
+                println!(
+                    "{} {}",
+                    "📖 mdBook summary written to:".green().bold(),
+                    format!("{}/SUMMARY.md", args.outdir).bright_white()
+                );
+                book_files = files;
+            }
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write SUMMARY.md:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    if let Some(catalog) = typst_catalog {
+        match catalog.finish() {
+            Ok(_) => typst::render_pdf(&args.outdir),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write Typst catalog:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    #[cfg(feature = "db")]
+    if let (Some(package_db), Some(path)) = (package_db, &args.db) {
+        match package_db.finish() {
+            Ok(()) => println!(
+                "{} {}",
+                "✅ Database written to:".green().bold(),
+                path.bright_white()
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write database:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let (Some(sqlite_export), Some(path)) = (sqlite_export, &args.sqlite) {
+        match sqlite_export.finish() {
+            Ok(()) => println!(
+                "{} {}",
+                "✅ SQLite database written to:".green().bold(),
+                path.bright_white()
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write SQLite database:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    if let (Some(stream), Some(path)) = (ndjson_stream, &args.ndjson) {
+        match stream.finish() {
+            Ok(_) => println!(
+                "{} {}",
+                "✅ NDJSON stream written to:".green().bold(),
+                path.bright_white()
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to write NDJSON stream:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    if !args.graph.is_empty() {
+        write_dependency_graphs(&dependency_graph, &args)?;
+    }
+    if !args.canvas.is_empty() {
+        write_canvases(
+            &dependency_graph,
+            &canvas_notes.into_inner().unwrap(),
+            &args,
+        )?;
+    }
+    if let Some(ref graph_json) = args.graph_json {
+        save_json(
+            graph_json,
+            &dependency_graph.adjacency(),
+            args.pretty_json(false),
+        )
+        .with_context(|| format!("failed to write dependency graph {}", graph_json))?;
+        println!(
+            "{} {}",
+            "🕸️  Dependency adjacency list written to:".green().bold(),
+            graph_json.bright_white()
+        );
+    }
+    if let Some(ref dot_output) = args.dot_output {
+        fs::write(dot_output, graph::render_full_dot(&dependency_graph))
+            .with_context(|| format!("failed to write dependency graph {}", dot_output))?;
+        println!(
+            "{} {}",
+            "🕸️  Dependency graph written to:".green().bold(),
+            dot_output.bright_white()
+        );
+    }
+
+    // Completion order depends on scheduling; sort so reruns diff cleanly
+    let mut package_errors = package_errors.into_inner().unwrap();
+    package_errors.sort();
+    let errors: Vec<ErrorSummary> = package_errors.iter().map(ErrorSummary::from).collect();
+    if let Err(e) = save_json(
+        &format!("{}/errors.json", args.outdir),
+        &errors,
+        args.pretty_json(true),
+    ) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to write errors.json:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+    if let Err(e) = save_json(
+        &format!("{}/failures.json", args.outdir),
+        &package_errors,
+        args.pretty_json(true),
+    ) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to write failures.json:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+
+    for (name, _) in &packages_vec {
+        failed_cache.remove(name);
+    }
+    for package_error in &package_errors {
+        if package_error.error == EVALUATION_FAILED || package_error.error == DERIVATION_NOT_FOUND {
+            failed_cache.insert(package_error.package.clone(), package_error.error.clone());
+        }
+    }
+    if let Err(e) = save_json(
+        &failed_cache::path(&args.outdir),
+        &failed_cache,
+        args.pretty_json(true),
+    ) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to write failed-cache.json:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+
+    if args.prune {
+        let notes = note_index::load(&args.outdir).notes;
+        match prune_stale_notes(
+            &args,
+            packages,
+            &rename_map,
+            &[&previous_notes, &notes],
+            args.archive,
+        ) {
+            Ok(pruned) => print_pruned(&pruned, args.archive),
+            Err(e) => eprintln!(
+                "{} {}",
+                "⚠️  Failed to prune stale notes:".yellow().bold(),
+                e.to_string().red()
+            ),
+        }
+    }
+
+    let mut failed_packages: Vec<String> = package_errors.into_iter().map(|e| e.package).collect();
+    failed_packages.dedup();
+
+    let summary = RunSummary {
+        revision: args.revision.clone(),
+        git_url: args.git_url.clone(),
+        nixpkgs_path: nixpkgs_path.clone(),
+        generated_at: Utc::now().to_rfc3339(),
+        total: packages.len(),
+        matched,
+        processed: sample_count,
+        errors: error_count.load(Ordering::Relaxed),
+        failed_packages,
+        known_failing,
+        resumed,
+        hook_failures: hook_failures.load(Ordering::Relaxed),
+        profile: args.profile.then(|| profiler.report()),
+    };
+    if let Some(ref profile) = summary.profile {
+        profile.print();
+    }
+    if let Err(e) = save_json(
+        &format!("{}/summary.json", args.outdir),
+        &summary,
+        args.pretty_json(true),
+    ) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to write summary.json:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+
+    let mut manifest = manifest::Manifest::default();
+    manifest.extend(generated_notes.into_inner().unwrap());
+    manifest.add_dir(&args.outdir, "graphs");
+    manifest.add_dir(&args.outdir, "canvases");
+    manifest.extend(book_files);
+    for report in [
+        "summary.json",
+        "errors.json",
+        "failures.json",
+        logging::LOG_FILE,
+        "index.md",
+        "index.json",
+        "index.html",
+        "catalog.typ",
+        "catalog.pdf",
+    ] {
+        manifest.add_if_exists(&args.outdir, report);
+    }
+    if let Err(e) = save_json(
+        &manifest::path(&args.outdir),
+        &manifest,
+        args.pretty_json(true),
+    ) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to write manifest.json:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+
+    println!("{}", "🎉 Done!".green());
+
+    if args.only_changed_notes_to_stdout {
+        let mut changed_notes = changed_notes.into_inner().unwrap();
+        changed_notes.sort();
+        for note_path in changed_notes {
+            println!("{}", note_path);
+        }
+    }
+
+    Ok(Some(summary))
+}
+
+/// `package_info_from_meta` plus everything the command line asks for that
+/// doesn't need the derivation.
+fn prepare_package_info(
+    name: &str,
+    info: &Value,
+    args: &Args,
+    rename_map: &[(String, String)],
+) -> PackageInfo {
+    let mut package_info = package_info_from_meta(name, info);
+    package_info.name = apply_rename_map(rename_map, name);
+    if args.redact_emails {
+        redact_emails(&mut package_info);
+    }
+    if args.max_description_length > 0 {
+        truncate_long_description(
+            &mut package_info,
+            args.max_description_length,
+            args.keep_full_description,
+        );
+    }
+    package_info.extracted = args
+        .extract
+        .iter()
+        .filter_map(|extract| {
+            let value = info.pointer(&extract.pointer)?;
+            Some(ExtractedField {
+                label: extract.label.clone(),
+                value: value.clone(),
+            })
+        })
+        .collect();
+    package_info
+}
+
+/// Builds the metadata part of a `PackageInfo` from its `packages.json`
+/// entry. Derivation fields are filled in later by `get_package_info`.
+fn package_info_from_meta(name: &str, info: &Value) -> PackageInfo {
+    let mut package_info = PackageInfo {
+        name: name.to_string(),
+        attribute: name.to_string(),
+        version: info["version"].as_str().unwrap_or("unknown").to_string(),
+        available: info["meta"]["available"].as_bool().unwrap_or(false),
+        // `meta.broken` is normally a bool, but a string explains why
+        broken: match &info["meta"]["broken"] {
+            Value::Bool(broken) => *broken,
+            Value::String(_) => true,
+            _ => false,
+        },
+        broken_reason: info["meta"]["broken"].as_str().map(|s| s.to_string()),
+        description: info["meta"]["description"].as_str().map(|s| s.to_string()),
+        homepage: info["meta"]["homepage"].as_str().map(|s| s.to_string()),
+        download_page: first_url(&info["meta"]["downloadPage"]),
+        repositories: repositories(&info["meta"]["repositories"]),
+        // The first license of a list, by short name if it has one
+        license_short_name: license_names(&info["meta"]["license"])
+            .first()
+            .copied()
+            .unwrap_or("unknown")
+            .to_string(),
+        long_description: info["meta"]["longDescription"]
+            .as_str()
+            .map(|s| s.to_string()),
+        long_description_truncated: false,
+        full_long_description: None,
+        maintainers: Vec::new(),
+        teams: Vec::new(),
+        position: info["meta"]["position"].as_str().map(|s| s.to_string()),
+        drv_path: String::new(),
+        drv_missing: false,
+        outputs: Vec::new(),
+        // `outputName` is the package's first output, installed when
+        // `meta.outputsToInstall` is absent
+        outputs_to_install: match string_list(&info["meta"]["outputsToInstall"]) {
+            outputs if outputs.is_empty() => info["outputName"]
+                .as_str()
+                .map(|name| vec![name.to_string()])
+                .unwrap_or_default(),
+            outputs => outputs,
+        },
+        output_paths: BTreeMap::new(),
+        output_sizes: None,
+        input_srcs: Vec::new(),
+        input_drvs: Vec::new(),
+        priority: info["meta"]["priority"].as_i64(),
+        platforms: string_list(&info["meta"]["platforms"]),
+        bad_platforms: string_list(&info["meta"]["badPlatforms"]),
+        dependencies: Vec::new(),
+        extracted: Vec::new(),
+        sources: Vec::new(),
+        raw_derivation: String::new(),
+        zettel_id: None,
+    };
+    parse_maintainers(&info["meta"], &mut package_info);
+    package_info
+}
+
+/// Reads a `--rename-map` file, returning its entries longest key first so
+/// the most specific prefix wins.
+fn load_rename_map(path: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("failed to read rename map at {}", path))?;
+    let map: BTreeMap<String, String> = serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse rename map at {}", path))?;
+    let mut entries: Vec<_> = map.into_iter().collect();
+    entries.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+    Ok(entries)
+}
+
+/// Renames an attribute: keys ending in `.` replace a matching prefix,
+/// other keys only replace an identical name.
+fn apply_rename_map(rename_map: &[(String, String)], name: &str) -> String {
+    for (from, to) in rename_map {
+        if name == from {
+            return to.clone();
+        }
+        if from.ends_with('.') {
+            if let Some(rest) = name.strip_prefix(from.as_str()) {
+                return format!("{}{}", to, rest);
+            }
+        }
+    }
+    name.to_string()
+}
+
+/// String entries of a JSON array, skipping anything that isn't a string
+/// (e.g. platform patterns given as attribute sets).
+fn string_list(value: &Value) -> Vec<String> {
+    value.as_array().map_or(Vec::new(), |arr| {
+        arr.iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect()
+    })
+}
+
+/// Short names and SPDX ids of `meta.license`, which is a license set, a
+/// list of them, or occasionally a plain string.
+fn license_names(license: &Value) -> Vec<&str> {
+    match license {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(licenses) => licenses.iter().flat_map(license_names).collect(),
+        Value::Object(_) => ["shortName", "spdxId"]
+            .iter()
+            .filter_map(|key| license[key].as_str())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether any of the licenses is marked `free = false`. Bare license
+/// names carry no such flag and count as free.
+fn is_unfree(license: &Value) -> bool {
+    match license {
+        Value::Array(licenses) => licenses.iter().any(is_unfree),
+        Value::Object(_) => license["free"] == Value::Bool(false),
+        _ => false,
+    }
+}
+
+/// A url given either as a string or as a list of mirrors.
+fn first_url(value: &Value) -> Option<String> {
+    match value {
+        Value::String(url) => Some(url.clone()),
+        Value::Array(urls) => urls
+            .iter()
+            .find_map(|url| url.as_str().map(|s| s.to_string())),
+        _ => None,
+    }
+}
+
+/// `meta.repositories` is an attribute set keyed by VCS (`git`, ...), but
+/// a bare url is accepted as well.
+fn repositories(value: &Value) -> BTreeMap<String, String> {
+    match value {
+        Value::Object(repositories) => repositories
+            .iter()
+            .filter_map(|(kind, url)| Some((kind.clone(), first_url(url)?)))
+            .collect(),
+        other => first_url(other)
+            .map(|url| BTreeMap::from([("repository".to_string(), url)]))
+            .unwrap_or_default(),
+    }
+}
+
+/// Top-level namespace of an attribute name, e.g. `python3Packages` for
+/// `python3Packages.requests`. Unqualified attributes share `top-level`.
+fn attribute_prefix(name: &str) -> &str {
+    name.split_once('.')
+        .map_or("top-level", |(prefix, _)| prefix)
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of
+/// characters, including dots, and `?` for exactly one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was and how much text it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character and try again
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Splits `meta.maintainers` and `meta.teams` into individual maintainers and
+/// teams. Team objects can show up in either list, so both are inspected.
+fn parse_maintainers(meta: &Value, package_info: &mut PackageInfo) {
+    let entries = meta["maintainers"]
+        .as_array()
+        .into_iter()
+        .chain(meta["teams"].as_array())
+        .flatten();
+
+    for entry in entries {
+        if let Some(members) = entry.get("members").and_then(|m| m.as_array()) {
+            let short_name = entry["shortName"]
+                .as_str()
+                .or_else(|| entry["scope"].as_str())
+                .unwrap_or("unknown")
+                .to_string();
+            if package_info
+                .teams
+                .iter()
+                .any(|t| t.short_name == short_name)
+            {
+                continue;
+            }
+            package_info.teams.push(Team {
+                short_name,
+                members: members.iter().filter_map(Maintainer::from_json).collect(),
+            });
+        } else if let Some(maintainer) = Maintainer::from_json(entry) {
+            let handle = maintainer.handle();
+            if !package_info
+                .maintainers
+                .iter()
+                .any(|m| m.handle() == handle)
+            {
+                package_info.maintainers.push(maintainer);
+            }
+        }
+    }
+}
+
+/// Drops maintainer email addresses so they never reach any output.
+fn redact_emails(package_info: &mut PackageInfo) {
+    let team_members = package_info.teams.iter_mut().flat_map(|t| &mut t.members);
+    for maintainer in package_info.maintainers.iter_mut().chain(team_members) {
+        maintainer.email = None;
+    }
+}
+
+/// Cuts the long description to `max_length` characters, keeping the full
+/// text aside with `keep_full` for the collapsed callout.
+fn truncate_long_description(package_info: &mut PackageInfo, max_length: usize, keep_full: bool) {
+    let Some(ref long_description) = package_info.long_description else {
+        return;
+    };
+    let Some(truncated) = truncate_at_boundary(long_description, max_length) else {
+        return;
+    };
+    let full = package_info.long_description.replace(truncated);
+    package_info.long_description_truncated = true;
+    if keep_full {
+        package_info.full_long_description = full;
+    }
+}
+
+/// Cuts `text` to at most `max_length` characters, preferring the end of a
+/// sentence and otherwise a word. Returns `None` if it already fits.
+fn truncate_at_boundary(text: &str, max_length: usize) -> Option<String> {
+    let (cut, _) = text.char_indices().nth(max_length)?;
+    let prefix = &text[..cut];
+
+    // Only back off to a boundary if that keeps at least half the text
+    let sentence_end = prefix
+        .rmatch_indices(['.', '!', '?'])
+        .map(|(index, _)| index + 1)
+        .find(|&end| prefix[end..].starts_with(char::is_whitespace) || end == prefix.len());
+    let word_end = prefix.rfind(char::is_whitespace);
+    let end = sentence_end
+        .or(word_end)
+        .filter(|&end| end >= prefix.len() / 2)
+        .unwrap_or(prefix.len());
+
+    Some(prefix[..end].trim_end().to_string())
+}
+
+/// Creates the output directory, or checks that an existing one may be
+/// overwritten, then cleans it if asked and copies the template files.
+fn prepare_outdir(args: &Args) -> Result<(), VaultError> {
+    // check if the output directory exists, if not create it
+    // if it holds output from a previous run ask the user if they want to
+    // overwrite it, unless --no-overwrite asks us to refuse outright or
+    // --force to go ahead
+    let outdir_exists = Path::new(&args.outdir).exists();
+    if args.no_overwrite && outdir_exists {
+        if !is_empty_dir(&args.outdir)? {
+            return Err(VaultError::OutputDirectoryNotEmpty(args.outdir.clone()));
+        }
+    } else if outdir_exists
+        && has_prior_output(&args.outdir)
+        && !(args.clean || args.force || args.resume)
+    {
+        // Nobody can answer the prompt, don't wait for an answer forever
+        if !std::io::stdin().is_terminal() {
+            return Err(VaultError::Aborted(format!(
+                "{} already contains a vault and stdin is not a terminal, pass --force to overwrite it",
+                args.outdir
+            )));
+        }
+        println!(
+            "{} {}",
+            "⚠️  Output directory already contains a vault:"
+                .yellow()
+                .bold(),
+            args.outdir.bright_white()
+        );
+        print!("{}", "⚠️  Do you want to continue? (y/n): ".yellow().bold());
+        // Ensure the prompt is displayed immediately
+        std::io::stdout()
+            .flush()
+            .map_err(|e| VaultError::io("failed to write to stdout", e))?;
+        let mut input = String::new();
+        std::io::stdin()
+            .read_line(&mut input)
+            .map_err(|e| VaultError::io("failed to read confirmation from stdin", e))?;
+
+        if input.trim().to_lowercase() != "y" {
+            return Err(VaultError::Aborted(
+                "not overwriting the existing vault".to_string(),
+            ));
+        }
+    } else if !outdir_exists {
+        std::fs::create_dir_all(&args.outdir).map_err(|e| {
+            VaultError::io(
+                format!("failed to create output directory {}", args.outdir),
+                e,
+            )
+        })?;
+        println!(
+            "{} {}",
+            "✅ Created output directory:".green().bold(),
+            args.outdir.bright_white()
+        );
+    }
+
+    if args.clean {
+        clean_previous_run(&args.outdir);
+    }
+
+    if args.clear_cache {
+        let cache = derivation_cache::root(&args.outdir);
+        if cache.exists() {
+            fs::remove_dir_all(&cache)
+                .map_err(|e| VaultError::io(format!("failed to clear {}", cache.display()), e))?;
+        }
+        println!("{}", "🧹 Cleared cached derivations.".cyan().bold());
+    }
+
+    // Copy template files to output directory
+    if let Err(e) = copy_template_files(&args.outdir) {
+        eprintln!(
+            "{} {}",
+            "⚠️  Failed to copy template files:".yellow().bold(),
+            e.to_string().red()
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the man page of the whole CLI, or with `out_dir` writes it and
+/// one page per subcommand there, as distributions install them.
+fn write_man_pages(out_dir: Option<&str>) -> Result<(), VaultError> {
+    let command = Args::command();
+    match out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)
+                .map_err(|e| VaultError::io(format!("failed to create {}", dir), e))?;
+            clap_mangen::generate_to(command, dir)
+                .map_err(|e| VaultError::io(format!("failed to write man pages to {}", dir), e))?;
+            println!(
+                "{} {}",
+                "📖 Man pages written to:".green().bold(),
+                dir.bright_white()
+            );
+        }
+        None => {
+            let mut page = Vec::new();
+            clap_mangen::Man::new(command)
+                .render(&mut page)
+                .and_then(|()| std::io::stdout().write_all(&page))
+                .map_err(|e| VaultError::io("failed to write the man page", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the files listed in the manifest of the previous run.
+fn clean_previous_run(outdir: &str) {
+    match manifest::Manifest::load(outdir) {
+        Some(previous) => println!(
+            "{} {}",
+            "🧹 Removed files of the previous run:".cyan().bold(),
+            previous.clean(outdir).to_string().bright_white()
+        ),
+        None => println!(
+            "{}",
+            "⚠️  No manifest.json from a previous run, nothing to clean."
+                .yellow()
+                .bold()
+        ),
+    }
+}
+
+/// The nixpkgs store path an earlier run recorded in summary.json, whose
+/// derivation cache `--offline` renders from.
+fn previous_nixpkgs_path(outdir: &str) -> Result<String, VaultError> {
+    let path = format!("{}/summary.json", outdir);
+    let data = fs::read_to_string(&path)
+        .map_err(|e| VaultError::io(format!("--offline needs {} from an earlier run", path), e))?;
+    let summary: Value = serde_json::from_str(&data).map_err(|source| VaultError::JsonParse {
+        path: path.clone(),
+        source,
+    })?;
+    summary["nixpkgs_path"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| {
+            anyhow!(
+                "{} predates --offline, generate the vault once more with network access",
+                path
+            )
+            .into()
+        })
+}
+
+/// Whether `outdir` holds results of an earlier run (a `packages.json` or at
+/// least one note), as opposed to a leftover `.obsidian` folder.
+fn has_prior_output(outdir: &str) -> bool {
+    find_packages_json(outdir).is_some()
+        || ["packages", "pages"].iter().any(|dir| {
+            fs::read_dir(format!("{}/{}", outdir, dir))
+                .is_ok_and(|mut entries| entries.next().is_some())
+        })
+}
+
+fn is_empty_dir(path: &str) -> anyhow::Result<bool> {
+    let mut entries =
+        fs::read_dir(path).with_context(|| format!("failed to read directory {}", path))?;
+    Ok(entries.next().is_none())
+}
+
+fn fetch_nixpkgs_with_nix(git_url: &str, revision: &str) -> Result<String, VaultError> {
+    let nix_expr = format!(
+        r#"builtins.fetchGit {{ url = "{}"; ref = "{}"; }}"#,
+        git_url, revision
+    );
+
+    // Create a spinner
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
+            .template("{spinner:.cyan} {msg}")
+            .unwrap(),
+    );
+    spinner.set_message("Fetching nixpkgs repository...");
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    let output = Command::new("nix-instantiate")
+        .args(["--eval", "--json", "--expr", &nix_expr])
+        .output();
+    spinner.finish_and_clear();
+    let output = output.map_err(|e| VaultError::io("failed to run nix-instantiate", e))?;
+
+    if !output.status.success() {
+        return Err(VaultError::NixInstantiateFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    println!("{}", "✅ Repository fetched successfully!".green());
+
+    let path = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .trim_matches('"')
+        .to_string();
+
+    Ok(path)
+}
+
+/// Files every nixpkgs checkout has; `packages-config.nix` is also what
+/// `generate_packages_json` evaluates against.
+const NIXPKGS_FILES: &[&str] = &[
+    "pkgs/top-level/all-packages.nix",
+    "pkgs/top-level/packages-config.nix",
+];
+
+/// Checks that `nixpkgs_path` is a nixpkgs checkout, naming everything that
+/// is missing when it isn't.
+fn analyze_nixpkgs(nixpkgs_path: &str) -> Result<(), VaultError> {
+    let root = Path::new(nixpkgs_path);
+    let mut missing: Vec<&str> = NIXPKGS_FILES
+        .iter()
+        .copied()
+        .filter(|file| !root.join(file).is_file())
+        .collect();
+    if !root.join("default.nix").is_file() && !root.join("flake.nix").is_file() {
+        missing.push("default.nix or flake.nix");
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidNixpkgs(format!(
+            "{} is missing {}",
+            nixpkgs_path,
+            missing.join(", ")
+        )))
+    }
+}
+
+/// The `sh -c` pipeline writing `outdir/packages.json`, with every path
+/// quoted as a single shell word.
+fn packages_json_command(
+    nixpkgs_path: &str,
+    system: Option<&str>,
+    outdir: &str,
+) -> anyhow::Result<String> {
+    // nix-env -f . -qa --meta --json --show-trace --arg config 'import ./pkgs/top-level/packages-config.nix' | jq -c '{"version":2,"packages":.}' > packages.json
+    let quote = |value: String| {
+        shlex::try_quote(&value)
+            .map(|quoted| quoted.into_owned())
+            .with_context(|| format!("cannot pass {:?} to the shell", value))
+    };
+    // nixpkgs takes the system to evaluate for as an argument of its own
+    let system = system.map_or(String::new(), |system| {
+        format!(" --argstr system {}", system)
+    });
+    let command = format!(
+        "nix-env -f {} -qa --meta --json --show-trace{} --arg config {} | jq -c '{{\"version\":2,\"packages\":.}}' > {}",
+        quote(nixpkgs_path.to_string())?,
+        system,
+        quote(format!(
+            "import {}/pkgs/top-level/packages-config.nix",
+            nixpkgs_path
+        ))?,
+        quote(format!("{}/packages.json", outdir))?
+    );
+    Ok(command)
+}
+
+fn generate_packages_json(
+    nixpkgs_path: &str,
+    system: Option<&str>,
+    outdir: &str,
+) -> anyhow::Result<()> {
+    let command = packages_json_command(nixpkgs_path, system, outdir)?;
+
+    // with spinner
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
+            .template("{spinner:.cyan} {msg}")
+            .unwrap(),
+    );
+    spinner.set_message("Computing packages.json...");
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    let output = Command::new("sh").arg("-c").arg(&command).output();
+    spinner.finish_and_clear();
+    let output = output.with_context(|| format!("failed to run command: {}", command))?;
+
+    if !output.status.success() {
+        bail!(
+            "failed to compute packages.json with `{}`: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    println!("{}", "✅ packages.json computed successfully!".green());
+    Ok(())
+}
+
+/// The `packages.json` of `outdir`, preferring the compressed copy written
+/// by --compress-packages-json.
+fn find_packages_json(outdir: &str) -> Option<String> {
+    [".gz", ""]
+        .iter()
+        .map(|suffix| format!("{}/packages.json{}", outdir, suffix))
+        .find(|path| Path::new(path).exists())
+}
+
+/// Parses a `packages.json`, decompressing it on the fly if it ends in `.gz`.
+fn load_packages_json(path: &str) -> Result<Value, VaultError> {
+    let file =
+        fs::File::open(path).map_err(|e| VaultError::io(format!("failed to read {}", path), e))?;
+    let reader: Box<dyn Read> = if path.ends_with(".gz") {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    serde_json::from_reader(BufReader::new(reader)).map_err(|source| VaultError::JsonParse {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Replaces `packages.json` with `packages.json.gz`.
+fn compress_packages_json(outdir: &str) -> anyhow::Result<()> {
+    let plain_path = format!("{}/packages.json", outdir);
+    let gz_path = format!("{}.gz", plain_path);
+
+    let mut plain =
+        fs::File::open(&plain_path).with_context(|| format!("failed to read {}", plain_path))?;
+    let gz = fs::File::create(&gz_path).with_context(|| format!("failed to create {}", gz_path))?;
+    let mut encoder =
+        flate2::write::GzEncoder::new(std::io::BufWriter::new(gz), flate2::Compression::default());
+    std::io::copy(&mut plain, &mut encoder)
+        .and_then(|_| encoder.finish()?.flush())
+        .with_context(|| format!("failed to compress {}", plain_path))?;
+    fs::remove_file(&plain_path).with_context(|| format!("failed to remove {}", plain_path))?;
+
+    println!(
+        "{} {}",
+        "🗜️  Compressed packages.json to:".green().bold(),
+        gz_path.bright_white()
+    );
+    Ok(())
+}
+
+/// Why `get_package_info` could not evaluate a package.
+#[derive(Debug)]
+enum EvaluationFailure {
+    /// `nix derivation show` failed, possibly only this time, with its stderr
+    Failed(String),
+    /// `nix derivation show` ran longer than `--eval-timeout` seconds
+    TimedOut(u64),
+    /// The attribute doesn't exist, or doesn't evaluate to a derivation,
+    /// with nix's stderr if it said so
+    NotFound(String),
+    /// `nix derivation show` printed something that isn't a derivation
+    InvalidJson(String),
+    /// `nix` couldn't be started
+    Spawn(String),
+    /// `--offline` and no earlier run cached the derivation
+    NotCached,
+}
+
+/// Category of a failure in `failures.json`.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum FailureKind {
+    Timeout,
+    Evaluation,
+    NotFound,
+    JsonParse,
+    Io,
+    NotCached,
+}
+
+impl EvaluationFailure {
+    /// Whether another attempt could succeed. A missing attribute stays
+    /// missing; anything else may be load, the daemon or the network.
+    fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            EvaluationFailure::NotFound(_) | EvaluationFailure::NotCached
+        )
+    }
+
+    fn kind(&self) -> FailureKind {
+        match self {
+            EvaluationFailure::Failed(_) => FailureKind::Evaluation,
+            EvaluationFailure::TimedOut(_) => FailureKind::Timeout,
+            EvaluationFailure::NotFound(_) => FailureKind::NotFound,
+            EvaluationFailure::InvalidJson(_) => FailureKind::JsonParse,
+            EvaluationFailure::Spawn(_) => FailureKind::Io,
+            EvaluationFailure::NotCached => FailureKind::NotCached,
+        }
+    }
+
+    /// What nix printed, or the parse or spawn error, for `failures.json`.
+    fn details(&self) -> Option<String> {
+        match self {
+            EvaluationFailure::Failed(details)
+            | EvaluationFailure::NotFound(details)
+            | EvaluationFailure::InvalidJson(details)
+            | EvaluationFailure::Spawn(details) => {
+                Some(details.trim().to_string()).filter(|details| !details.is_empty())
+            }
+            EvaluationFailure::TimedOut(_) | EvaluationFailure::NotCached => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EvaluationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvaluationFailure::Failed(_) => f.write_str(EVALUATION_FAILED),
+            EvaluationFailure::NotFound(_) => f.write_str(DERIVATION_NOT_FOUND),
+            EvaluationFailure::InvalidJson(_) => f.write_str("invalid derivation JSON"),
+            EvaluationFailure::Spawn(_) => f.write_str("failed to run nix"),
+            EvaluationFailure::NotCached => f.write_str("not in the derivation cache"),
+            EvaluationFailure::TimedOut(seconds) => {
+                write!(f, "evaluation timed out after {}s", seconds)
+            }
+        }
+    }
+}
+
+fn get_package_info(
+    package_name: &str,
+    nixpkgs_path: &str,
+    system: Option<&str>,
+    eval_timeout: u64,
+    retries: usize,
+    package_info: &mut PackageInfo,
+) -> Result<(), EvaluationFailure> {
+    let Some(installable) = installable(nixpkgs_path, package_name) else {
+        return Err(EvaluationFailure::NotFound(String::new()));
+    };
+
+    let installables = [installable];
+    let mut attempt = 0;
+    let derivation_json = loop {
+        match derivation_show(&installables, system, eval_timeout) {
+            Err(failure) if failure.is_retryable() && attempt < retries => {
+                tracing::debug!(package = package_name, %failure, attempt, "retrying");
+                std::thread::sleep(retry_delay(attempt));
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+
+    apply_raw_derivation(derivation_json, package_info)
+}
+
+/// Wait before retry `attempt`: 500ms, 1s, 2s, ... up to 32s, each
+/// randomly stretched or shrunk by up to half, so packages that failed
+/// together under load don't all come back at the same moment.
+fn retry_delay(attempt: usize) -> Duration {
+    use std::hash::BuildHasher;
+
+    let base = 500u64 << attempt.min(6);
+    // Every RandomState is seeded differently, good enough for jitter
+    let random = std::collections::hash_map::RandomState::new().hash_one(attempt);
+    Duration::from_millis(base / 2 + random % base)
+}
+
+/// Fills in `package_info` from the `nix derivation show` output of a
+/// single package.
+fn apply_raw_derivation(
+    derivation_json: String,
+    package_info: &mut PackageInfo,
+) -> Result<(), EvaluationFailure> {
+    // The output is an object where keys are drv paths
+    let parsed_json = serde_json::from_str::<serde_json::Value>(&derivation_json).map_err(|e| {
+        tracing::debug!(error = %e, "unparsable derivation JSON");
+        EvaluationFailure::InvalidJson(e.to_string())
+    })?;
+    // Get the first (and usually only) derivation
+    let Some((drv_path, drv_data)) = parsed_json.as_object().and_then(|d| d.iter().next()) else {
+        return Err(EvaluationFailure::InvalidJson(
+            "expected an object of derivations".to_string(),
+        ));
+    };
+    apply_derivation(drv_path, drv_data, derivation_json, package_info);
+    Ok(())
+}
+
+/// Nix system names look like `x86_64-linux`; anything else would end up
+/// unquoted in the evaluation command.
+fn parse_system(value: &str) -> Result<String, String> {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        Ok(value.to_string())
+    } else {
+        Err(format!("`{}` is not a nix system like x86_64-linux", value))
+    }
+}
+
+/// `nixpkgs_path#package_name`. Attribute names with a NUL byte can't be
+/// passed to nix at all.
+fn installable(nixpkgs_path: &str, package_name: &str) -> Option<String> {
+    (!package_name.contains('\0')).then(|| format!("{}#{}", nixpkgs_path, package_name))
+}
+
+/// `--eval-timeout` for `child::output_with_timeout`, where 0 is no limit.
+fn nix_timeout(eval_timeout: u64) -> Option<Duration> {
+    (eval_timeout != 0).then(|| Duration::from_secs(eval_timeout))
+}
+
+/// Runs `nix derivation show` on installables, returning its JSON output.
+fn derivation_show(
+    installables: &[String],
+    system: Option<&str>,
+    eval_timeout: u64,
+) -> Result<String, EvaluationFailure> {
+    let mut command = Command::new("nix");
+    command
+        .args(["derivation", "show", "--impure"])
+        .args(system.iter().flat_map(|system| ["--system", system]))
+        .args(installables)
+        .env("NIXPKGS_ALLOW_UNFREE", "1")
+        .env("NIXPKGS_ALLOW_INSECURE", "1")
+        .env("NIXPKGS_ALLOW_BROKEN", "1")
+        .env("NIXPKGS_ALLOW_UNSUPPORTED_SYSTEM", "1");
+
+    tracing::debug!(?command, "evaluating");
+    let start = Instant::now();
+    let output = match child::output_with_timeout(&mut command, nix_timeout(eval_timeout)) {
+        Ok(Some(output)) => output,
+        Ok(None) => {
+            tracing::debug!(?command, "killed after the evaluation timeout");
+            return Err(EvaluationFailure::TimedOut(eval_timeout));
+        }
+        Err(e) => {
+            // Command execution failed
+            tracing::warn!(?command, error = %e, "failed to run nix");
+            return Err(EvaluationFailure::Spawn(e.to_string()));
+        }
+    };
+    tracing::debug!(
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        status = %output.status,
+        installables = installables.len(),
+        "evaluated"
+    );
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        tracing::trace!(%stderr, "evaluation failed");
+        // The flake and plain attribute set wordings of a missing attribute
+        if stderr.contains("does not provide attribute")
+            || (stderr.contains("error: attribute '") && stderr.contains("' missing"))
+        {
+            return Err(EvaluationFailure::NotFound(stderr));
+        }
+        // Otherwise it may be an evaluation error, or just the daemon or
+        // network acting up
+        return Err(EvaluationFailure::Failed(stderr));
+    }
+
+    let derivation_json = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    // Skip empty or malformed JSON
+    if derivation_json.trim().is_empty() || derivation_json.trim() == "{}" {
+        return Err(EvaluationFailure::NotFound(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(derivation_json)
+}
+
+/// Evaluates a batch of `(attribute, packages.json entry)` pairs with a
+/// single `nix derivation show`, which saves the evaluator startup for all
+/// but one of them. The output is keyed by drv path only, so derivations
+/// are matched back to attributes by derivation name. Attributes missing
+/// from the result, because the batch failed as a whole or their name is
+/// shared with another derivation in it, have to be evaluated on their own.
+fn process_packages_batch(
+    packages: &[(&String, &Value)],
+    nixpkgs_path: &str,
+    system: Option<&str>,
+    eval_timeout: u64,
+) -> HashMap<String, (String, Value)> {
+    let Some(installables) = packages
+        .iter()
+        .map(|(name, _)| installable(nixpkgs_path, name))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return HashMap::new();
+    };
+    // The whole batch gets the time its packages would have had one by one
+    let timeout = eval_timeout.saturating_mul(packages.len() as u64);
+    let derivation_json = match derivation_show(&installables, system, timeout) {
+        Ok(derivation_json) => derivation_json,
+        Err(failure) => {
+            tracing::debug!(packages = packages.len(), %failure, "batch failed, evaluating one by one");
+            return HashMap::new();
+        }
+    };
+    let Ok(Value::Object(derivations)) = serde_json::from_str(&derivation_json) else {
+        return HashMap::new();
+    };
+
+    let mut by_name: HashMap<&str, Vec<(&String, &Value)>> = HashMap::new();
+    for (drv_path, drv_data) in &derivations {
+        if let Some(name) = drv_data["name"].as_str() {
+            by_name.entry(name).or_default().push((drv_path, drv_data));
+        }
+    }
+
+    packages
+        .iter()
+        .filter_map(|(attribute, info)| {
+            // Aliases of one derivation share it, which is fine
+            match by_name.get(info["name"].as_str()?)?.as_slice() {
+                [(drv_path, drv_data)] => Some((
+                    attribute.to_string(),
+                    (drv_path.to_string(), (*drv_data).clone()),
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// What evaluating packages the way a run does takes: the nix calls, the
+/// derivation cache and the permits bounding concurrent evaluations.
+struct Evaluator<'a> {
+    args: &'a Args,
+    nixpkgs_path: &'a str,
+    cache: Option<&'a DerivationCache>,
+    eval_jobs: &'a Semaphore,
+}
+
+/// Derivations of one `--batch-size` chunk, looked up in the cache and
+/// evaluated in a single batch for the rest, before its packages are filled
+/// in one by one.
+struct ChunkEvaluation<'a> {
+    evaluator: &'a Evaluator<'a>,
+    cached: HashMap<&'a str, String>,
+    batch: HashMap<String, (String, Value)>,
+    /// Time of the cache lookups and the batch, per package of the chunk
+    share: Duration,
+}
+
+impl<'a> Evaluator<'a> {
+    fn chunk(&'a self, chunk: &[(&'a String, &'a Value)]) -> ChunkEvaluation<'a> {
+        let start = Instant::now();
+        let cached: HashMap<&str, String> = match self.cache {
+            Some(cache) => chunk
+                .iter()
+                .filter_map(|(name, _)| Some((name.as_str(), cache.get(name)?)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let uncached: Vec<(&String, &Value)> = chunk
+            .iter()
+            .filter(|(name, _)| !cached.contains_key(name.as_str()))
+            .copied()
+            .collect();
+        let batch = if uncached.len() > 1 && !self.args.offline {
+            let _permit = self.eval_jobs.acquire();
+            process_packages_batch(
+                &uncached,
+                self.nixpkgs_path,
+                self.args.system.as_deref(),
+                self.args.eval_timeout,
+            )
+        } else {
+            HashMap::new()
+        };
+        ChunkEvaluation {
+            evaluator: self,
+            cached,
+            batch,
+            share: start.elapsed() / chunk.len().max(1) as u32,
+        }
+    }
+}
+
+impl ChunkEvaluation<'_> {
+    /// Fills in the derivation fields of `package_info`, from the cache or
+    /// the batch if they have it and otherwise with a call of its own.
+    /// Returns the outcome and whether the derivation came from the cache.
+    fn evaluate(
+        &mut self,
+        name: &str,
+        package_info: &mut PackageInfo,
+    ) -> (Result<(), EvaluationFailure>, bool) {
+        let Evaluator {
+            args,
+            nixpkgs_path,
+            eval_jobs,
+            ..
+        } = self.evaluator;
+        // A cache entry that no longer parses is evaluated again
+        let from_cache = self.cached.remove(name).is_some_and(|derivation_json| {
+            apply_raw_derivation(derivation_json, package_info).is_ok()
+        });
+        let evaluation = match self.batch.remove(name) {
+            _ if from_cache => Ok(()),
+            Some((drv_path, drv_data)) => {
+                let mut raw_derivation = serde_json::Map::new();
+                raw_derivation.insert(drv_path.clone(), drv_data);
+                let raw_derivation = Value::Object(raw_derivation);
+                apply_derivation(
+                    &drv_path,
+                    &raw_derivation[&drv_path],
+                    format!("{}\n", raw_derivation),
+                    package_info,
+                );
+                Ok(())
+            }
+            None if args.offline => Err(EvaluationFailure::NotCached),
+            None => {
+                let _permit = eval_jobs.acquire();
+                get_package_info(
+                    name,
+                    nixpkgs_path,
+                    args.system.as_deref(),
+                    args.eval_timeout,
+                    args.retries,
+                    package_info,
+                )
+            }
+        };
+        (evaluation, from_cache)
+    }
+}
+
+/// Fills in the fields of `package_info` that come from its derivation.
+fn apply_derivation(
+    drv_path: &str,
+    drv_data: &Value,
+    raw_derivation: String,
+    package_info: &mut PackageInfo,
+) {
+    // Set the drv path
+    package_info.drv_path = drv_path.to_string();
+    package_info.raw_derivation = raw_derivation;
+
+    // Extract outputs
+    if let Some(outputs) = drv_data.get("outputs").and_then(|o| o.as_object()) {
+        package_info.outputs = outputs.keys().cloned().collect();
+        if package_info.outputs_to_install.is_empty() {
+            package_info.outputs_to_install =
+                package_info.outputs.iter().take(1).cloned().collect();
+        }
+        package_info.output_paths = outputs
+            .iter()
+            .filter_map(|(name, output)| {
+                let path = output.get("path")?.as_str()?;
+                Some((name.clone(), path.to_string()))
+            })
+            .collect();
+    }
+
+    // Extract inputDrvs
+    if let Some(input_drvs) = drv_data.get("inputDrvs").and_then(|i| i.as_object()) {
+        package_info.input_drvs = input_drvs.keys().cloned().collect();
+    }
+
+    // Extract inputSrcs
+    if let Some(input_srcs) = drv_data.get("inputSrcs").and_then(|i| i.as_array()) {
+        package_info.input_srcs = input_srcs
+            .iter()
+            .filter_map(|s| s.as_str().map(|s| s.to_string()))
+            .collect();
+    }
+
+    // Store paths of `src`/`srcs`; urls and hashes need --sources
+    let env = &drv_data["env"];
+    package_info.sources = env["src"]
+        .as_str()
+        .into_iter()
+        .chain(
+            env["srcs"]
+                .as_str()
+                .into_iter()
+                .flat_map(str::split_whitespace),
+        )
+        .filter(|path| !path.is_empty())
+        .map(Source::from_store_path)
+        .collect();
+
+    // Dependencies are essentially the inputDrvs (store paths of dependencies)
+    package_info.dependencies = package_info.input_drvs.clone();
+}
+
+/// Lists `srcs` (or `src`) of a package. fetchurl exposes `urls`, git and
+/// other VCS fetchers `url` and `rev`; local sources are plain paths.
+const SOURCES_EXPR: &str = r#"p:
+  let
+    srcs = if p.srcs or null != null then p.srcs else if p.src or null != null then [ p.src ] else [ ];
+    source = s:
+      if builtins.isAttrs s then {
+        urls = s.urls or (if s ? url then [ s.url ] else [ ]);
+        hash = s.outputHash or null;
+        rev = s.rev or null;
+        path = s.outPath or null;
+      } else {
+        urls = [ ];
+        hash = null;
+        rev = null;
+        path = toString s;
+      };
+  in map source (if builtins.isList srcs then srcs else [ srcs ])"#;
+
+/// Replaces the store-path-only `sources` from the derivation with the
+/// evaluated `src` attributes. Packages whose sources fail to evaluate
+/// within `--eval-timeout` keep what the derivation had.
+fn fetch_sources(
+    package_name: &str,
+    nixpkgs_path: &str,
+    system: Option<&str>,
+    eval_timeout: u64,
+    package_info: &mut PackageInfo,
+) {
+    let mut command = Command::new("nix");
+    command
+        .args(["eval", "--json", "--impure"])
+        .args(system.iter().flat_map(|system| ["--system", system]))
+        .arg(format!("{}#{}", nixpkgs_path, package_name))
+        .args(["--apply", SOURCES_EXPR])
+        .env("NIXPKGS_ALLOW_UNFREE", "1")
+        .env("NIXPKGS_ALLOW_INSECURE", "1")
+        .env("NIXPKGS_ALLOW_BROKEN", "1")
+        .env("NIXPKGS_ALLOW_UNSUPPORTED_SYSTEM", "1");
+    let Ok(Some(output)) = child::output_with_timeout(&mut command, nix_timeout(eval_timeout))
+    else {
+        return;
+    };
+    if !output.status.success() {
+        return;
+    }
+    if let Ok(Value::Array(sources)) = serde_json::from_slice(&output.stdout) {
+        package_info.sources = sources.iter().map(Source::from_json).collect();
+    }
+}
+
+/// Fills `derivation_hash` of every source with an evaluated hash from the
+/// fixed-output derivation among `input_drvs` that produces it. Evaluation
+/// already wrote those derivations to the store, so nothing is built, but
+/// reading them takes an evaluation job and `--eval-timeout` all the same.
+fn verify_source_hashes(package_info: &mut PackageInfo, eval_jobs: &Semaphore, eval_timeout: u64) {
+    if package_info.input_drvs.is_empty()
+        || package_info
+            .sources
+            .iter()
+            .all(|source| source.hash.is_none() || source.store_path.is_none())
+    {
+        return;
+    }
+
+    let mut command = Command::new("nix");
+    command
+        .args(["derivation", "show"])
+        .args(&package_info.input_drvs);
+    let permit = eval_jobs.acquire();
+    let output = child::output_with_timeout(&mut command, nix_timeout(eval_timeout));
+    drop(permit);
+    let Ok(Some(output)) = output else {
+        return;
+    };
+    if !output.status.success() {
+        return;
+    }
+    let Ok(Value::Object(derivations)) = serde_json::from_slice(&output.stdout) else {
+        return;
+    };
+
+    // Output path -> outputHash as written in the nix expression
+    let mut output_hashes = HashMap::new();
+    for derivation in derivations.values() {
+        let Some(hash) = derivation["env"]["outputHash"].as_str() else {
+            continue;
+        };
+        if let Some(outputs) = derivation["outputs"].as_object() {
+            for output in outputs.values() {
+                if let Some(path) = output["path"].as_str() {
+                    output_hashes.insert(path.to_string(), hash.to_string());
+                }
+            }
+        }
+    }
+
+    for source in &mut package_info.sources {
+        if let Some(ref path) = source.store_path {
+            source.derivation_hash = output_hashes.get(path).cloned();
+        }
+    }
+}
+
+/// Fills `output_sizes` using `nix path-info -S`, one evaluation job and
+/// `--eval-timeout` per output. Outputs that haven't been built or
+/// substituted yet, or whose query timed out, are left out of the map.
+fn measure_output_sizes(package_info: &mut PackageInfo, eval_jobs: &Semaphore, eval_timeout: u64) {
+    let mut sizes = BTreeMap::new();
+    for (output, path) in &package_info.output_paths {
+        let mut command = Command::new("nix");
+        command.args(["path-info", "--closure-size", path]);
+        let _permit = eval_jobs.acquire();
+        let Ok(Some(result)) = child::output_with_timeout(&mut command, nix_timeout(eval_timeout))
+        else {
+            continue;
+        };
+        if !result.status.success() {
+            continue;
+        }
+        let stdout = String::from_utf8_lossy(&result.stdout);
+        if let Some(size) = stdout
+            .split_whitespace()
+            .last()
+            .and_then(|s| s.parse().ok())
+        {
+            sizes.insert(output.clone(), size);
+        }
+    }
+    package_info.output_sizes = Some(sizes);
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// /nix/store/abc123-package-name-1.0.drv -> abc123-package-name-1.0
+fn drv_filename(drv_path: &str) -> &str {
+    let name = drv_path.strip_prefix("/nix/store/").unwrap_or(drv_path);
+    name.strip_suffix(".drv").unwrap_or(name)
+}
+
+/// Path of a package's note relative to `packages/`, without extension.
+/// Layouts named after the attribute path use it after `--rename-map`.
+fn note_stem(package_info: &PackageInfo, layout: Layout) -> String {
+    match layout {
+        Layout::Flat => drv_filename(&package_info.drv_path).to_string(),
+        // python3Packages.requests -> python3Packages/requests. Quoted
+        // attribute names may contain `/` or produce empty segments.
+        Layout::AttrTree => attr_tree_stem(&package_info.name),
+        Layout::Dendron => dendron_stem(&package_info.name),
+        Layout::Zettel => zettel_stem(
+            package_info
+                .zettel_id
+                .as_deref()
+                .expect("zettel IDs are assigned before notes are written"),
+            &package_info.name,
+        ),
+    }
+}
+
+/// python3Packages.requests -> python3Packages/requests. Quoted attribute
+/// names may contain `/` or produce empty segments.
+fn attr_tree_stem(attribute: &str) -> String {
+    attribute
+        .split('.')
+        .map(|segment| match segment {
+            "" => "_".to_string(),
+            segment => segment.replace('/', "_"),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// python3Packages.requests -> python3Packages.requests, with `/` and empty
+/// segments of quoted attribute names replaced as for `attr_tree_stem`.
+fn dendron_stem(attribute: &str) -> String {
+    attr_tree_stem(attribute).replace('/', ".")
+}
+
+/// 952948746399, python3Packages.requests ->
+/// 952948746399-python3Packages.requests
+fn zettel_stem(id: &str, attribute: &str) -> String {
+    format!("{}-{}", id, dendron_stem(attribute))
+}
+
+/// Removes, or archives, the notes below packages/ of packages missing from
+/// `packages`. With `--layout attr-tree` or `dendron` a note's path names
+/// its attribute, and with `zettel` its attribute and the ID in
+/// zettel-ids.json.
+/// Flat notes are named after the derivation, so `indexes`, attribute to
+/// note path and oldest first, say whose they are; a flat note is stale if
+/// none of its packages is left or all of them got a newer note since.
+/// Flat notes no index mentions are kept.
+fn prune_stale_notes(
+    args: &Args,
+    packages: &serde_json::Map<String, Value>,
+    rename_map: &[(String, String)],
+    indexes: &[&HashMap<String, String>],
+    archive: bool,
+) -> std::io::Result<Vec<String>> {
+    match args.layout {
+        Layout::AttrTree | Layout::Dendron | Layout::Zettel => {
+            let zettel_ids = zettel::ZettelIds::load(&args.outdir);
+            let stems: HashSet<String> = packages
+                .keys()
+                .filter_map(|name| {
+                    let renamed = apply_rename_map(rename_map, name);
+                    match args.layout {
+                        Layout::Dendron => Some(dendron_stem(&renamed)),
+                        Layout::Zettel => Some(zettel_stem(zettel_ids.get(name)?, &renamed)),
+                        _ => Some(attr_tree_stem(&renamed)),
+                    }
+                })
+                .collect();
+            prune::prune(&args.outdir, args.notes_dir(), archive, |stem| {
+                !stems.contains(stem)
+            })
+        }
+        Layout::Flat => {
+            let mut owners: HashMap<&str, Vec<&str>> = HashMap::new();
+            let mut latest: HashMap<&str, &str> = HashMap::new();
+            for index in indexes {
+                for (package, note) in index.iter() {
+                    let stem = prune::stem(args.notes_dir(), note);
+                    owners.entry(stem).or_default().push(package.as_str());
+                    latest.insert(package.as_str(), stem);
+                }
+            }
+            prune::prune(&args.outdir, args.notes_dir(), archive, |stem| {
+                owners.get(stem).is_some_and(|owners| {
+                    owners.iter().all(|package| {
+                        !packages.contains_key(*package) || latest.get(package) != Some(&stem)
+                    })
+                })
+            })
+        }
+    }
+}
+
+fn print_pruned(pruned: &[String], archive: bool) {
+    let label = if archive {
+        format!("🗄️  Stale notes moved to {}/:", prune::ARCHIVE_DIR)
+    } else {
+        "🧹 Stale notes removed:".to_string()
+    };
+    println!(
+        "{} {}",
+        label.cyan().bold(),
+        pruned.len().to_string().bright_white()
+    );
+}
+
+/// Prints the note every package of a dry run would get, and whether it
+/// exists already. Flat note names come from the drv path, so they are only
+/// known for packages an earlier run wrote a note for; the others show `-`.
+/// Returns how many existing notes would be overwritten.
+fn print_dry_run_plan(
+    packages: &[(&String, &Value)],
+    args: &Args,
+    rename_map: &[(String, String)],
+) -> usize {
+    let previous_notes = note_index::load(&args.outdir).notes;
+    // The IDs a real run would assign, without recording them
+    let mut zettel_ids = zettel::ZettelIds::load(&args.outdir);
+    if args.layout == Layout::Zettel {
+        zettel_ids.assign(packages.iter().map(|(name, _)| name.as_str()));
+    }
+    let mut overwritten = 0;
+    for (name, _) in packages {
+        let renamed = apply_rename_map(rename_map, name);
+        let note = match (args.format, args.layout) {
+            (OutputFormat::Typst, _) => Some("catalog.typ".to_string()),
+            (_, Layout::AttrTree) => Some(format!(
+                "{}/{}.{}",
+                args.notes_dir(),
+                attr_tree_stem(&renamed),
+                args.notes_extension()
+            )),
+            (_, Layout::Dendron) => Some(format!(
+                "{}/{}.{}",
+                args.notes_dir(),
+                dendron_stem(&renamed),
+                args.notes_extension()
+            )),
+            (_, Layout::Zettel) => zettel_ids.get(name).map(|id| {
+                format!(
+                    "{}/{}.{}",
+                    args.notes_dir(),
+                    zettel_stem(id, &renamed),
+                    args.notes_extension()
+                )
+            }),
+            (_, Layout::Flat) => previous_notes.get(name.as_str()).cloned(),
+        };
+        let exists = note
+            .as_ref()
+            .is_some_and(|note| Path::new(&args.outdir).join(note).is_file());
+        if exists {
+            overwritten += 1;
+        }
+        println!(
+            "dry-run note={} attribute={} status={}",
+            note.as_deref().unwrap_or("-"),
+            name,
+            if exists { "overwrite" } else { "new" }
+        );
+    }
+    overwritten
+}
+
+/// Path of a package's note relative to the output directory, without
+/// extension. Logseq reads page names from file names its own way.
+fn note_base(package_info: &PackageInfo, args: &Args) -> String {
+    let stem = note_stem(package_info, args.layout);
+    match args.format {
+        OutputFormat::Logseq => format!("{}/{}", args.notes_dir(), logseq::file_name(&stem)),
+        _ => format!("{}/{}", args.notes_dir(), stem),
+    }
+}
+
+/// Path of a package's note relative to the output directory.
+fn note_path(package_info: &PackageInfo, args: &Args) -> String {
+    format!(
+        "{}.{}",
+        note_base(package_info, args),
+        args.notes_extension()
+    )
+}
+
+/// Writes the derivation JSON exactly as nix printed it to
+/// `packages/<note>.drv.json`, next to the package's note.
+fn save_derivation_json(package_info: &PackageInfo, args: &Args) -> Result<(), std::io::Error> {
+    let path = format!("{}/{}.drv.json", args.outdir, note_base(package_info, args));
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_if_changed(&path, &package_info.raw_derivation).map(|_| ())
+}
+
+/// Writes the note for a package, returning its path relative to the output
+/// directory if the note was created or its content changed.
+fn save_package_note(
+    package_info: &PackageInfo,
+    args: &Args,
+    template: Option<&NoteTemplate>,
+) -> Result<Option<String>, std::io::Error> {
+    if args.dry_run {
+        return Ok(None);
+    }
+    let relative_path = note_path(package_info, args);
+
+    let note_content = match args.format {
+        // Generate the Obsidian note content
+        OutputFormat::Markdown if args.frontmatter_style != FrontmatterStyle::Obsidian => {
+            site::keep_unchanged_date(
+                site::convert_links(
+                    &site::with_front_matter(
+                        args.frontmatter_style,
+                        package_info,
+                        &package_note_markdown(
+                            package_info,
+                            args.notes_extension(),
+                            args,
+                            template,
+                        ),
+                    ),
+                    &relative_path,
+                    args.notes_extension(),
+                ),
+                &format!("{}/{}", args.outdir, relative_path),
+            )
+        }
+        OutputFormat::Markdown if args.mdbook => mdbook::render_page(
+            &package_note_markdown(package_info, args.notes_extension(), args, template),
+            &relative_path,
+            args.notes_extension(),
+        ),
+        OutputFormat::Markdown => {
+            package_note_markdown(package_info, args.notes_extension(), args, template)
+        }
+        OutputFormat::Json => to_json(package_info, args.pretty_json(false))?,
+        OutputFormat::Logseq => match template {
+            Some(template) => {
+                template.render(&template_context(package_info, args.notes_extension()))
+            }
+            None => logseq::render_page(package_info),
+        },
+        OutputFormat::Org => match template {
+            Some(template) => {
+                template.render(&template_context(package_info, args.notes_extension()))
+            }
+            None => org::render_page(package_info, args.notes_extension()),
+        },
+        OutputFormat::Html => html::render_page(
+            &package_info.name,
+            &package_note_markdown(package_info, args.link_extension(), args, template),
+            &relative_path,
+        ),
+        OutputFormat::Typst => unreachable!("Typst pages are streamed into a single catalog"),
+    };
+
+    write_note(&relative_path, &note_content, &args.outdir)
+}
+
+/// Markdown of a package's note, from `--template` if one was given.
+fn package_note_markdown(
+    package_info: &PackageInfo,
+    extension: &str,
+    args: &Args,
+    template: Option<&NoteTemplate>,
+) -> String {
+    match template {
+        Some(template) => template.render(&template_context(package_info, extension)),
+        None => generate_package_note_template(package_info, extension, args.frontmatter),
+    }
+}
+
+/// Fields a `--template` can use: the package record, plus ready-made links
+/// to the notes of its dependencies.
+fn template_context(package_info: &PackageInfo, extension: &str) -> Value {
+    let mut context = serde_json::to_value(package_info).unwrap_or_default();
+    context["dependency_links"] = package_info
+        .dependencies
+        .iter()
+        .map(|dependency| {
+            let label = graph::store_path_name(dependency);
+            serde_json::json!({
+                "note": drv_filename(dependency),
+                "label": label,
+                "link": note_link(drv_filename(dependency), Some(label), extension),
+            })
+        })
+        .collect();
+    context
+}
+
+/// Writes a note below `outdir`, creating its directory, and returns
+/// `relative_path` if the note was created or its content changed.
+fn write_note(
+    relative_path: &str,
+    content: &str,
+    outdir: &str,
+) -> Result<Option<String>, std::io::Error> {
+    let note_path = format!("{}/{}", outdir, relative_path);
+    if let Some(parent) = Path::new(&note_path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let changed = write_if_changed(&note_path, content)?;
+    Ok(changed.then(|| relative_path.to_string()))
+}
+
+/// Rewrites `[[<drv>]]` links to the vault-relative path of the note for that
+/// derivation. Full paths keep links unambiguous when the same leaf name
+/// appears under several attribute sets. Links to derivations without a note
+/// are left alone.
+fn resolve_note_links(content: &str, links: &HashMap<&str, &str>, extension: &str) -> String {
+    let suffix = format!(".{}", extension);
+    let mut resolved = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start..].find("]]") else {
+            break;
+        };
+        let link = &rest[start..start + length + 2];
+        let (target, label) = match link[2..link.len() - 2].split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (&link[2..link.len() - 2], None),
+        };
+        resolved.push_str(&rest[..start]);
+        match links.get(target.strip_suffix(&suffix).unwrap_or(target)) {
+            Some(path) => resolved.push_str(&note_link(path, label, extension)),
+            None => resolved.push_str(link),
+        }
+        rest = &rest[start + link.len()..];
+    }
+    resolved.push_str(rest);
+    resolved
+}
+
+/// Runs `--post-note-hook` for a written note, describing why it failed.
+fn run_post_note_hook(hook: &[String], note_path: &str) -> Result<(), String> {
+    let output = Command::new(&hook[0])
+        .args(&hook[1..])
+        .arg(note_path)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.trim() {
+        "" => Err(output.status.to_string()),
+        stderr => Err(format!("{}: {}", output.status, stderr)),
+    }
+}
+
+/// Writes `content` unless the file already holds the same content, ignoring
+/// the generation timestamp footer. Returns whether the file was written.
+fn write_if_changed(path: &str, content: &str) -> Result<bool, std::io::Error> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        if strip_generated_footer(&existing) == strip_generated_footer(content) {
+            return Ok(false);
+        }
+    }
+    fs::write(path, content)?;
+    Ok(true)
+}
+
+fn strip_generated_footer(content: &str) -> &str {
+    content
+        .rfind(GENERATED_FOOTER)
+        .map_or(content, |index| &content[..index])
+}
+
+fn write_dependency_graphs(dependency_graph: &DependencyGraph, args: &Args) -> anyhow::Result<()> {
+    let graphs_dir = format!("{}/graphs", args.outdir);
+    fs::create_dir_all(&graphs_dir)
+        .with_context(|| format!("failed to create graphs directory {}", graphs_dir))?;
+
+    for root in &args.graph {
+        let Some(drv_path) = dependency_graph.find_by_name(root) else {
+            eprintln!(
+                "{} {}",
+                "⚠️  Package not processed, skipping graph:".yellow().bold(),
+                root.bright_white()
+            );
+            continue;
+        };
+
+        let graph_path = format!("{}/{}.{}", graphs_dir, root, args.graph_format.extension());
+        let content = graph::render(
+            args.graph_format,
+            dependency_graph,
+            drv_path,
+            &args.exclude_deps_of,
+            None,
+        );
+        fs::write(&graph_path, content)
+            .with_context(|| format!("failed to write dependency graph {}", graph_path))?;
+        println!(
+            "{} {}",
+            "🕸️  Dependency graph written to:".green().bold(),
+            graph_path.bright_white()
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes `canvases/<name>.canvas` for every `--canvas` package or package
+/// set, linking the cards to the notes written in this run.
+fn write_canvases(
+    dependency_graph: &DependencyGraph,
+    notes: &HashMap<String, String>,
+    args: &Args,
+) -> anyhow::Result<()> {
+    let canvases_dir = format!("{}/canvases", args.outdir);
+    fs::create_dir_all(&canvases_dir)
+        .with_context(|| format!("failed to create canvases directory {}", canvases_dir))?;
+
+    for name in &args.canvas {
+        let canvas = match dependency_graph.find_by_name(name) {
+            Some(drv_path) => canvas::neighborhood(dependency_graph, notes, drv_path),
+            None => match canvas::package_set(dependency_graph, notes, name) {
+                Some(canvas) => canvas,
+                None => {
+                    eprintln!(
+                        "{} {}",
+                        "⚠️  Package or package set not processed, skipping canvas:"
+                            .yellow()
+                            .bold(),
+                        name.bright_white()
+                    );
+                    continue;
+                }
+            },
+        };
+
+        let canvas_path = format!("{}/{}.canvas", canvases_dir, name);
+        save_json(&canvas_path, &canvas, args.pretty_json(true))
+            .with_context(|| format!("failed to write canvas {}", canvas_path))?;
+        println!(
+            "{} {}",
+            "🗺️  Canvas written to:".green().bold(),
+            canvas_path.bright_white()
+        );
+    }
+
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+fn save_json<T: Serialize>(path: &str, value: &T, pretty: bool) -> Result<(), std::io::Error> {
+    fs::write(path, to_json(value, pretty)?)
+}
+
+fn copy_template_files(outdir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "📁 Copying template files...".cyan().bold());
+
+    let mut files_copied = 0;
+
+    for file_path in TemplateAssets::iter() {
+        let file_data = TemplateAssets::get(&file_path).unwrap();
+        let content = file_data.data.as_ref();
+
+        // Create the full output path
+        let output_path = format!("{}/{}", outdir, file_path);
+
+        // Create parent directories if they don't exist
+        if let Some(parent) = Path::new(&output_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write the file
+        fs::write(&output_path, content)?;
+        files_copied += 1;
+
+        println!("{} {}", "  ✅".green(), file_path.bright_white());
+    }
+
+    if files_copied > 0 {
+        println!(
+            "{} {} {}",
+            "✅ Copied".green().bold(),
+            files_copied.to_string().bright_white(),
+            "template files".green().bold()
+        );
+    } else {
+        println!("{}", "ℹ️  No template files found to copy".yellow());
+    }
+
+    Ok(())
+}
+
+/// `## ⬅️ Reverse Dependencies` section linking the processed packages that
+/// depend on a note's package.
+fn reverse_dependencies_section(
+    dependents: &[&str],
+    dependency_graph: &DependencyGraph,
+    extension: &str,
+) -> String {
+    let mut section = String::from("## ⬅️ Reverse Dependencies\n\n");
+    for dependent in dependents {
+        section.push_str(&format!(
+            "- {}\n",
+            note_link(
+                drv_filename(dependent),
+                Some(dependency_graph.label(dependent)),
+                extension
+            )
+        ));
+    }
+    section.push('\n');
+    section
+}
+
+/// Inserts a section into a generated note, right above its footer.
+fn insert_before_footer(content: &str, section: &str) -> String {
+    let index = content.rfind(GENERATED_FOOTER).unwrap_or(content.len());
+    format!("{}{}{}", &content[..index], section, &content[index..])
+}
+
+/// Wikilink to another note, shown as `label` if given. Obsidian resolves
+/// bare `[[name]]` links to `.md` files only, so any other extension has to
+/// be spelled out.
+fn note_link(target: &str, label: Option<&str>, extension: &str) -> String {
+    let target = if extension == "md" {
+        target.to_string()
+    } else {
+        format!("{}.{}", target, extension)
+    };
+    match label {
+        Some(label) => format!("[[{}|{}]]", target, label),
+        None => format!("[[{}]]", target),
+    }
+}
+
+/// Markdown for a `--extract` value, or `None` for `null`.
+fn render_extracted_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(if *b { "✅ Yes" } else { "❌ No" }.to_string()),
+        Value::Number(n) => Some(format!("`{}`", n)),
+        Value::String(s) => Some(s.clone()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(s) => Some(format!("`{}`", s)),
+                    Value::Null => None,
+                    other => Some(format!("`{}`", other)),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Value::Object(_) => Some(format!("`{}`", value)),
+    }
+}
+
+/// A double-quoted YAML scalar. JSON string escapes are valid YAML ones.
+fn yaml_string(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+/// A front matter list of strings, `[]` rather than null when empty.
+fn yaml_list(key: &str, values: impl IntoIterator<Item = String>) -> String {
+    let items: String = values
+        .into_iter()
+        .map(|value| format!("  - {}\n", yaml_string(&value)))
+        .collect();
+    if items.is_empty() {
+        format!("{}: []\n", key)
+    } else {
+        format!("{}:\n{}", key, items)
+    }
+}
+
+/// Items of a list `yaml_list` wrote, e.g. `aliases` or `tags`. Notes of
+/// older versions have them unquoted, which is read as is.
+fn front_matter_list(front_matter: &str, key: &str) -> Vec<String> {
+    let header = format!("{}:", key);
+    front_matter
+        .lines()
+        .skip_while(|line| *line != header)
+        .skip(1)
+        .map_while(|line| line.strip_prefix("  - "))
+        .map(|item| serde_json::from_str(item).unwrap_or_else(|_| item.to_string()))
+        .collect()
+}
+
+/// Tags of a package's note: status tags, then derivation checks, then
+/// license, maintainers, teams and outputs.
+fn note_tags(package_info: &PackageInfo) -> Vec<String> {
+    let mut tags = Vec::new();
+
+    // Add conditional tags
+    if package_info.broken {
+        tags.push("broken".to_string());
+    }
+    if !package_info.available {
+        tags.push("not-available".to_string());
+    }
+    if package_info.drv_missing {
+        tags.push("missing-drv".to_string());
+    }
+    if package_info.sources.iter().any(Source::hash_mismatch) {
+        tags.push("hash-mismatch".to_string());
+    }
+    if package_info
+        .priority
+        .is_some_and(|priority| priority > DEFAULT_PRIORITY)
+    {
+        tags.push("low-priority".to_string());
+    }
+    tags.push(format!("license/{}", package_info.license_short_name));
+
+    // Add maintainer tags
+    for maintainer in &package_info.maintainers {
+        tags.push(format!(
+            "maintainers/{}",
+            maintainer.handle().replace(char::is_whitespace, "-")
+        ));
+    }
+
+    // Add team tags
+    for team in &package_info.teams {
+        tags.push(format!("teams/{}", team.short_name));
+    }
+
+    // Add output tags
+    for output in &package_info.outputs {
+        tags.push(format!("outputs/{}", output));
+    }
+    tags
+}
+
+fn generate_package_note_template(
+    package_info: &PackageInfo,
+    extension: &str,
+    frontmatter: bool,
+) -> String {
+    let mut content = String::new();
+
+    // Front matter with aliases and tags
+    content.push_str("---\n");
+    if frontmatter {
+        // Properties for Dataview and the like; tags stay the last key
+        content.push_str(&format!("name: {}\n", yaml_string(&package_info.name)));
+        content.push_str(&format!(
+            "version: {}\n",
+            yaml_string(&package_info.version)
+        ));
+        content.push_str(&format!("available: {}\n", package_info.available));
+        content.push_str(&format!("broken: {}\n", package_info.broken));
+        content.push_str(&format!(
+            "license: {}\n",
+            yaml_string(&package_info.license_short_name)
+        ));
+        content.push_str(&yaml_list(
+            "platforms",
+            package_info.platforms.iter().cloned(),
+        ));
+        content.push_str(&yaml_list(
+            "maintainers",
+            package_info.maintainers.iter().map(Maintainer::handle),
+        ));
+        if let Some(ref homepage) = package_info.homepage {
+            content.push_str(&format!("homepage: {}\n", yaml_string(homepage)));
+        }
+    }
+    // The attribute is only listed when --rename-map changed the name
+    let mut aliases = vec![package_info.name.clone()];
+    if package_info.attribute != package_info.name {
+        aliases.push(package_info.attribute.clone());
+    }
+    content.push_str(&yaml_list("aliases", aliases));
+    content.push_str(&yaml_list("tags", note_tags(package_info)));
+    content.push_str("---\n\n");
+
+    // Title
+    content.push_str(&format!("# {}\n\n", package_info.name));
+
+    // Description section (if available)
+    if let Some(ref long_desc) = package_info.long_description {
+        content.push_str("## 📝 Description\n\n");
+        content.push_str(long_desc);
+        if package_info.long_description_truncated {
+            match package_info.homepage {
+                Some(ref homepage) => content.push_str(&format!(" … [Read more]({})", homepage)),
+                None => content.push_str(" …"),
+            }
+        }
+        content.push_str("\n\n");
+        if let Some(ref full) = package_info.full_long_description {
+            content.push_str("> [!note]- Full description\n");
+            for line in full.trim_end().lines() {
+                if line.is_empty() {
+                    content.push_str(">\n");
+                } else {
+                    content.push_str(&format!("> {}\n", line));
+                }
+            }
+            content.push('\n');
+        }
+    } else if let Some(ref description) = package_info.description {
+        content.push_str("## 📝 Description\n\n");
+        content.push_str(description);
+        content.push_str("\n\n");
+    }
+
+    // Package Information section
+    content.push_str("## 📋 Package Information\n\n");
+    content.push_str(&format!("- **Name**: `{}`\n", package_info.name));
+
+    if !package_info.version.is_empty() {
+        content.push_str(&format!("- **Version**: `{}`\n", package_info.version));
+    }
+
+    content.push_str(&format!(
+        "- **Available**: {}\n",
+        if package_info.available {
+            "✅ Yes"
+        } else {
+            "❌ No"
+        }
+    ));
+    content.push_str(&format!(
+        "- **Broken**: {}\n",
+        match (package_info.broken, &package_info.broken_reason) {
+            (true, Some(reason)) => format!("⚠️ Yes ({})", reason),
+            (true, None) => "⚠️ Yes".to_string(),
+            (false, _) => "✅ No".to_string(),
+        }
+    ));
+
+    if let Some(ref description) = package_info.description {
+        content.push_str(&format!("- **Description**: {}\n", description));
+    }
+
+    if let Some(ref homepage) = package_info.homepage {
+        content.push_str(&format!("- **Homepage**: [{}]({})\n", homepage, homepage));
+    }
+
+    if let Some(ref download_page) = package_info.download_page {
+        content.push_str(&format!(
+            "- **Download Page**: [{}]({})\n",
+            download_page, download_page
+        ));
+    }
+
+    for (kind, url) in &package_info.repositories {
+        // Bare urls are stored under `repository`, which needs no qualifier
+        if kind == "repository" {
+            content.push_str(&format!("- **Repository**: [{}]({})\n", url, url));
+        } else {
+            content.push_str(&format!(
+                "- **Repository** ({}): [{}]({})\n",
+                kind, url, url
+            ));
+        }
+    }
+
+    if !package_info.license_short_name.is_empty() {
+        content.push_str(&format!(
+            "- **License**: `{}`\n",
+            package_info.license_short_name
+        ));
+    }
+
+    if let Some(priority) = package_info.priority {
+        content.push_str(&format!(
+            "- **Priority**: `{}`{}\n",
+            priority,
+            match priority.cmp(&DEFAULT_PRIORITY) {
+                std::cmp::Ordering::Greater => " (low, loses file collisions)",
+                std::cmp::Ordering::Less => " (high, wins file collisions)",
+                std::cmp::Ordering::Equal => " (default)",
+            }
+        ));
+    }
+
+    // Platforms
+    if !package_info.platforms.is_empty() {
+        content.push_str(&format!(
+            "- **Platforms**: {}\n",
+            package_info
+                .platforms
+                .iter()
+                .map(|p| format!("`{}`", p))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if !package_info.bad_platforms.is_empty() {
+        content.push_str(&format!(
+            "- **Broken on**: {}\n",
+            package_info
+                .bad_platforms
+                .iter()
+                .map(|p| format!("`{}`", p))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    for field in &package_info.extracted {
+        if let Some(value) = render_extracted_value(&field.value) {
+            content.push_str(&format!("- **{}**: {}\n", field.label, value));
+        }
+    }
+
+    // Maintainers section
+    if !package_info.maintainers.is_empty() {
+        content.push_str("## 👥 Maintainers\n\n");
+        for maintainer in &package_info.maintainers {
+            content.push_str(&format!("- {}", maintainer.markdown()));
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+
+    // Teams section
+    if !package_info.teams.is_empty() {
+        content.push_str("## 🏢 Teams\n\n");
+        for team in &package_info.teams {
+            content.push_str(&format!("- **{}**", team.short_name));
+            if !team.members.is_empty() {
+                content.push_str(&format!(
+                    ": {}",
+                    team.members
+                        .iter()
+                        .map(|m| m.display())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+
+    content.push('\n');
+
+    // Build Information section
+    content.push_str("## 🔧 Build Information\n\n");
+    content.push_str(&format!(
+        "- **Derivation Path**: `{}`{}\n",
+        package_info.drv_path,
+        if package_info.drv_missing {
+            " ⚠️ missing from the store"
+        } else {
+            ""
+        }
+    ));
+
+    if let Some(ref position) = package_info.position {
+        content.push_str(&format!("- **Source Position**: `{}`\n", position));
+    }
+
+    if !package_info.outputs.is_empty() {
+        content.push_str("- **Outputs** (✓ installed by default):\n");
+        for output in &package_info.outputs {
+            let installed = if package_info.outputs_to_install.contains(output) {
+                " ✓"
+            } else {
+                ""
+            };
+            // Content-addressed outputs have no path until they are built
+            match package_info.output_paths.get(output) {
+                Some(path) => {
+                    content.push_str(&format!("  - `{}`{}:  `{}`\n", output, installed, path))
+                }
+                None => content.push_str(&format!("  - `{}`{}\n", output, installed)),
+            }
+        }
+    }
+
+    if let Some(ref output_sizes) = package_info.output_sizes {
+        content.push_str("\n### 📦 Output Sizes\n\n");
+        content.push_str("| Output | Closure size |\n");
+        content.push_str("| --- | --- |\n");
+        for output in &package_info.outputs {
+            let size = output_sizes
+                .get(output)
+                .map_or("not in store".to_string(), |size| format_size(*size));
+            content.push_str(&format!("| `{}` | {} |\n", output, size));
+        }
+    }
+
+    content.push('\n');
+
+    // Source section
+    if !package_info.sources.is_empty() {
+        content.push_str("## 📦 Source\n\n");
+        for source in &package_info.sources {
+            let in_store = source
+                .store_path
+                .as_ref()
+                .is_some_and(|path| path.starts_with("/nix/store/"));
+            let label = match (&source.store_path, source.urls.first()) {
+                (Some(path), _) if in_store => graph::store_path_name(path),
+                // Local sources such as `./patches` are only a path
+                (Some(path), _) => path.as_str(),
+                (None, Some(url)) => url.as_str(),
+                (None, None) => "unknown",
+            };
+            content.push_str(&format!("- `{}`\n", label));
+            for url in &source.urls {
+                content.push_str(&format!("  - **URL**: <{}>\n", url));
+            }
+            if let Some(ref rev) = source.rev {
+                content.push_str(&format!("  - **Revision**: `{}`\n", rev));
+            }
+            if let Some(ref hash) = source.hash {
+                let verified = match source.derivation_hash {
+                    Some(_) if source.hash_mismatch() => " ⚠️ differs from the derivation",
+                    Some(_) => " ✅ matches the derivation",
+                    None => "",
+                };
+                content.push_str(&format!("  - **Hash**: `{}`{}\n", hash, verified));
+            }
+            if let Some(derivation_hash) = source
+                .derivation_hash
+                .as_ref()
+                .filter(|_| source.hash_mismatch())
+            {
+                content.push_str(&format!("  - **Derivation Hash**: `{}`\n", derivation_hash));
+            }
+            if let Some(path) = source.store_path.as_ref().filter(|_| in_store) {
+                content.push_str(&format!("  - **Store Path**: `{}`\n", path));
+            }
+        }
+        content.push('\n');
+    }
+
+    // Dependencies (with links to other notes)
+    if !package_info.dependencies.is_empty() {
+        content.push_str("## 🔗 Dependencies\n\n");
+        // Links point at the hashed note name but read as name and version;
+        // variants sharing both are told apart by their hash
+        let labels: Vec<&str> = package_info
+            .dependencies
+            .iter()
+            .map(|dep| graph::store_path_name(dep))
+            .collect();
+        for (dep, label) in package_info.dependencies.iter().zip(&labels) {
+            let dep_name = drv_filename(dep);
+            let label = if labels.iter().filter(|other| *other == label).count() > 1 {
+                let hash = dep_name.split_once('-').map_or(dep_name, |(hash, _)| hash);
+                format!("{} ({})", label, &hash[..hash.len().min(8)])
+            } else {
+                label.to_string()
+            };
+
+            // Create Obsidian link to dependency note
+            content.push_str(&format!(
+                "- {}\n",
+                note_link(dep_name, Some(&label), extension)
+            ));
+        }
+        content.push('\n');
+    }
+
+    // Input sources
+    if !package_info.input_srcs.is_empty() {
+        content.push_str("## 📁 Input Sources\n\n");
+        for src in &package_info.input_srcs {
+            content.push_str(&format!("- `{}`\n", src));
+        }
+        content.push('\n');
+    }
+
+    // Footer with generation timestamp
+    content.push_str(GENERATED_FOOTER);
+    content.push_str(&format!(
+        "{}*\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renamed_attributes_name_their_notes() {
+        let rename_map = vec![("python311Packages.".to_string(), "py.".to_string())];
+        let mut package_info =
+            package_info_from_meta("python311Packages.requests", &json!({ "meta": {} }));
+        package_info.name = apply_rename_map(&rename_map, &package_info.attribute);
+        package_info.zettel_id = Some("952948746399".to_string());
+
+        assert_eq!(note_stem(&package_info, Layout::AttrTree), "py/requests");
+        assert_eq!(note_stem(&package_info, Layout::Dendron), "py.requests");
+        assert_eq!(
+            note_stem(&package_info, Layout::Zettel),
+            "952948746399-py.requests"
+        );
+    }
+
+    #[test]
+    fn adversarial_paths_stay_single_shell_words() {
+        let nixpkgs_path = "/tmp/nix pkgs/$(touch pwned)/`id`;'\"";
+        let outdir = "out dir/$HOME";
+        let command = packages_json_command(nixpkgs_path, Some("x86_64-linux"), outdir).unwrap();
+
+        let words = shlex::split(&command).unwrap();
+        assert_eq!(words[2], nixpkgs_path);
+        let config = words.iter().position(|word| word == "config").unwrap();
+        assert_eq!(
+            words[config + 1],
+            format!("import {}/pkgs/top-level/packages-config.nix", nixpkgs_path)
+        );
+        assert_eq!(words.last().unwrap(), &format!("{}/packages.json", outdir));
+        assert!(packages_json_command("/tmp/nul\0", None, outdir).is_err());
+    }
+
+    #[test]
+    fn adversarial_attribute_names_are_passed_verbatim() {
+        // nix gets the installable as one argument, no shell involved
+        for name in ["foo bar", "a$b", "`id`", "x;rm -rf ~", "\"quoted\".attr"] {
+            assert_eq!(
+                installable("/nix/store/nixpkgs", name).as_deref(),
+                Some(format!("/nix/store/nixpkgs#{}", name).as_str())
+            );
+        }
+        assert_eq!(installable("/nix/store/nixpkgs", "nul\0byte"), None);
+
+        let mut package_info = package_info_from_meta("nul\0byte", &json!({ "meta": {} }));
+        let result = get_package_info(
+            "nul\0byte",
+            "/nix/store/nixpkgs",
+            None,
+            0,
+            0,
+            &mut package_info,
+        );
+        assert!(matches!(result, Err(EvaluationFailure::NotFound(_))));
+    }
+
+    /// Entry of `attribute` in fixtures/packages.json.
+    fn fixture_package(attribute: &str) -> Value {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/packages.json");
+        load_packages_json(path).unwrap()["packages"][attribute].clone()
+    }
+
+    #[test]
+    fn license_comes_from_meta() {
+        let package_info = package_info_from_meta("hello", &fixture_package("hello"));
+        assert_eq!(package_info.license_short_name, "gpl3Plus");
+
+        let info =
+            json!({ "meta": { "license": [{ "spdxId": "MIT" }, { "shortName": "asl20" }] } });
+        assert_eq!(package_info_from_meta("x", &info).license_short_name, "MIT");
+        let info = json!({ "meta": { "license": "custom" } });
+        assert_eq!(
+            package_info_from_meta("x", &info).license_short_name,
+            "custom"
+        );
+        let info = json!({ "meta": {} });
+        assert_eq!(
+            package_info_from_meta("x", &info).license_short_name,
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn availability_is_read_without_negation() {
+        let package_info = package_info_from_meta("hello", &fixture_package("hello"));
+        assert!(package_info.available);
+        let note = generate_package_note_template(&package_info, "md", false);
+        assert!(note.contains("- **Available**: ✅ Yes\n"));
+        assert!(!note_tags(&package_info).contains(&"not-available".to_string()));
+
+        let info = json!({ "meta": { "available": false } });
+        let package_info = package_info_from_meta("x", &info);
+        assert!(!package_info.available);
+        let note = generate_package_note_template(&package_info, "md", false);
+        assert!(note.contains("- **Available**: ❌ No\n"));
+        assert!(note.contains("  - \"not-available\"\n"));
+    }
+
+    /// A fresh directory for `test` below the system temp directory.
+    fn outdir(test: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("nixpkgs-vault-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn force_overwrites_an_existing_vault_without_asking() {
+        let outdir = outdir("force");
+        fs::write(format!("{}/packages.json", outdir), "{}").unwrap();
+        assert!(has_prior_output(&outdir));
+
+        let args = Args::parse_from(["nixpkgs-vault", "--outdir", &outdir, "--force"]);
+        prepare_outdir(&args).unwrap();
+        let args = Args::parse_from(["nixpkgs-vault", "--outdir", &outdir, "--yes"]);
+        prepare_outdir(&args).unwrap();
+        let args = Args::parse_from(["nixpkgs-vault", "--outdir", &outdir, "--no-overwrite"]);
+        assert!(matches!(
+            prepare_outdir(&args),
+            Err(VaultError::OutputDirectoryNotEmpty(_))
+        ));
+        assert!(Args::try_parse_from(["nixpkgs-vault", "--force", "--no-overwrite"]).is_err());
+        fs::remove_dir_all(outdir).unwrap();
+    }
+
+    #[test]
+    fn front_matter_holds_properties_then_tags() {
+        let package_info = package_info_from_meta("hello", &fixture_package("hello"));
+        let note = generate_package_note_template(&package_info, "md", true);
+        let front_matter = note.split("---\n").nth(1).unwrap();
+        assert_eq!(
+            front_matter,
+            "name: \"hello\"\n\
+             version: \"2.12.1\"\n\
+             available: true\n\
+             broken: false\n\
+             license: \"gpl3Plus\"\n\
+             platforms:\n  - \"aarch64-darwin\"\n  - \"aarch64-linux\"\n  \
+             - \"x86_64-darwin\"\n  - \"x86_64-linux\"\n\
+             maintainers:\n  - \"stv0g\"\n\
+             homepage: \"https://www.gnu.org/software/hello/manual/\"\n\
+             aliases:\n  - \"hello\"\n\
+             tags:\n  - \"license/gpl3Plus\"\n  - \"maintainers/stv0g\"\n"
+        );
+
+        let without = generate_package_note_template(&package_info, "md", false);
+        assert!(without.starts_with("---\naliases:\n"));
+    }
+
+    #[test]
+    fn front_matter_values_are_quoted() {
+        let info = json!({ "version": "1.0: \"beta\"", "meta": { "broken": true } });
+        let package_info = package_info_from_meta("x", &info);
+        let note = generate_package_note_template(&package_info, "md", true);
+        assert!(note.contains("version: \"1.0: \\\"beta\\\"\"\n"));
+        assert!(note.contains("license: \"unknown\"\n"));
+        assert!(note.contains("platforms: []\nmaintainers: []\n"));
+        assert_eq!(
+            note_tags(&package_info),
+            ["broken", "not-available", "license/unknown"]
+        );
+    }
+
+    #[test]
+    fn front_matter_parses_back_to_the_original_values() {
+        let info = json!({
+            "version": "1.0: \"beta\"",
+            "meta": {
+                "license": { "shortName": "custom: #1" },
+                "platforms": ["x86_64-linux", "- aarch64-darwin"],
+                "maintainers": [{ "name": "Alice", "github": "alice: \"a\"" }],
+                "homepage": "https://example.org/#top",
+            },
+        });
+        let mut package_info = package_info_from_meta("python311Packages.requests", &info);
+        package_info.name = "py: \"requests\"".to_string();
+        let note = generate_package_note_template(&package_info, "md", true);
+        let front_matter = note.split("---\n").nth(1).unwrap();
+        let parsed: serde_yaml::Mapping = serde_yaml::from_str(front_matter).unwrap();
+        let string = |key: &str| parsed[key].as_str().unwrap().to_string();
+        let list =
+            |key: &str| -> Vec<String> { serde_yaml::from_value(parsed[key].clone()).unwrap() };
+
+        assert_eq!(string("name"), "py: \"requests\"");
+        assert_eq!(string("version"), "1.0: \"beta\"");
+        assert_eq!(string("license"), "custom: #1");
+        assert_eq!(list("platforms"), ["x86_64-linux", "- aarch64-darwin"]);
+        assert_eq!(list("maintainers"), ["alice: \"a\""]);
+        assert_eq!(string("homepage"), "https://example.org/#top");
+        assert_eq!(
+            list("aliases"),
+            ["py: \"requests\"", "python311Packages.requests"]
+        );
+        assert_eq!(list("tags"), note_tags(&package_info));
+        assert_eq!(front_matter_list(front_matter, "aliases"), list("aliases"));
+    }
+
+    #[test]
+    fn only_missing_attributes_are_not_retried() {
+        assert!(!EvaluationFailure::NotFound(String::new()).is_retryable());
+        assert!(!EvaluationFailure::NotCached.is_retryable());
+        assert!(EvaluationFailure::Failed(String::new()).is_retryable());
+        assert!(EvaluationFailure::TimedOut(30).is_retryable());
+        assert!(EvaluationFailure::Spawn(String::new()).is_retryable());
+        assert!(EvaluationFailure::InvalidJson(String::new()).is_retryable());
+    }
+
+    #[test]
+    fn retry_delays_double_with_jitter_up_to_a_cap() {
+        for attempt in 0..10 {
+            let base = 500 << attempt.min(6);
+            let delay = retry_delay(attempt).as_millis() as u64;
+            assert!((base / 2..base * 3 / 2).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn maintainers_render_as_name_and_linked_handle() {
+        let maintainer = Maintainer::from_json(&json!({
+            "name": "Alice",
+            "github": "alice",
+            "email": "alice@example.org",
+            "githubId": 1
+        }))
+        .unwrap();
+        assert_eq!(maintainer.handle(), "alice");
+        assert_eq!(
+            maintainer.markdown(),
+            "Alice ([@alice](https://github.com/alice), alice@example.org)"
+        );
+
+        let maintainer = Maintainer::from_json(&json!({ "github": "bob" })).unwrap();
+        assert_eq!(maintainer.markdown(), "[@bob](https://github.com/bob)");
+
+        // The legacy string form has no GitHub handle
+        let maintainer = Maintainer::from_json(&json!("Carol <carol@example.org>")).unwrap();
+        assert_eq!(maintainer.name.as_deref(), Some("Carol"));
+        assert_eq!(maintainer.email.as_deref(), Some("carol@example.org"));
+        assert_eq!(maintainer.handle(), "Carol");
+        assert_eq!(maintainer.markdown(), "Carol (carol@example.org)");
+        let maintainer = Maintainer::from_json(&json!("Dave")).unwrap();
+        assert_eq!(maintainer.markdown(), "Dave");
+
+        assert!(Maintainer::from_json(&json!(42)).is_none());
+        let maintainer = Maintainer::from_json(&json!({})).unwrap();
+        assert_eq!(maintainer.markdown(), "unknown");
+    }
+
+    #[test]
+    fn nixpkgs_checkouts_are_told_apart_by_their_files() {
+        let root = outdir("analyze-nixpkgs");
+        let touch = |file: &str| {
+            let path = Path::new(&root).join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{ }\n").unwrap();
+        };
+
+        let missing = |root: &str| match analyze_nixpkgs(root) {
+            Err(VaultError::InvalidNixpkgs(message)) => message,
+            other => panic!("expected InvalidNixpkgs, got {:?}", other),
+        };
+        assert_eq!(
+            missing(&root),
+            format!(
+                "{} is missing pkgs/top-level/all-packages.nix, \
+                 pkgs/top-level/packages-config.nix, default.nix or flake.nix",
+                root
+            )
+        );
+        touch("pkgs/top-level/all-packages.nix");
+        touch("flake.nix");
+        assert_eq!(
+            missing(&root),
+            format!("{} is missing pkgs/top-level/packages-config.nix", root)
+        );
+        touch("pkgs/top-level/packages-config.nix");
+        analyze_nixpkgs(&root).unwrap();
+        fs::remove_file(format!("{}/flake.nix", root)).unwrap();
+        touch("default.nix");
+        analyze_nixpkgs(&root).unwrap();
+
+        let not_a_checkout = format!("{}/missing", root);
+        assert!(missing(&not_a_checkout).ends_with("default.nix or flake.nix"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Context;
+use std::fs::File;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+
+/// Log of a run, next to the notes. The terminal keeps the progress bar and
+/// the summary; per-package details only go here.
+pub const LOG_FILE: &str = "nixpkgs-vault.log";
+
+/// How much detail ends up in the log: warnings with `--quiet`, failures and
+/// phases by default, commands and timings with `-v`, everything with `-vv`.
+pub fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    }
+}
+
+/// Starts logging to `outdir/nixpkgs-vault.log`, replacing the log of the
+/// previous run. Only the first call in a process installs the logger.
+pub fn init(outdir: &str, level: LevelFilter) -> anyhow::Result<()> {
+    if tracing::dispatcher::has_been_set() {
+        return Ok(());
+    }
+    let path = format!("{}/{}", outdir, LOG_FILE);
+    let file = File::create(&path).with_context(|| format!("failed to create {}", path))?;
+    let _ = tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_thread_ids(true)
+        .with_max_level(level)
+        .try_init();
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::{drv_filename, format_size, PackageInfo};
+use chrono::Utc;
+
+/// File name Logseq reads back as `page`, in its default `:triple-lowbar`
+/// file name format: `/` starts a namespace and is written as `___`,
+/// characters file systems reject and `%` are percent-encoded.
+pub fn file_name(page: &str) -> String {
+    let mut name = String::new();
+    for c in page.chars() {
+        match c {
+            '/' => name.push_str("___"),
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' | '#' | '%' => {
+                name.push_str(&format!("%{:02X}", c as u32))
+            }
+            c if c.is_control() => name.push_str(&format!("%{:02X}", c as u32)),
+            c => name.push(c),
+        }
+    }
+    name
+}
+
+/// Property values end at the line break.
+fn property(content: &mut String, key: &str, value: &str) {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !value.is_empty() {
+        content.push_str(&format!("{}:: {}\n", key, value));
+    }
+}
+
+/// A top-level block with a heading, followed by its children.
+fn section(content: &mut String, heading: &str, children: &[String]) {
+    if children.is_empty() {
+        return;
+    }
+    content.push_str(&format!("- ## {}\n", heading));
+    for child in children {
+        content.push_str(&format!("\t- {}\n", child));
+    }
+}
+
+/// Paragraphs of a description, one block each.
+fn paragraphs(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+/// Renders a package as a Logseq page: metadata as page properties, the
+/// rest as an outline. Dependencies link to the pages of their notes.
+pub fn render_page(package_info: &PackageInfo) -> String {
+    let mut content = String::new();
+
+    property(&mut content, "name", &package_info.name);
+    property(&mut content, "attribute", &package_info.attribute);
+    property(&mut content, "version", &package_info.version);
+    property(&mut content, "license", &package_info.license_short_name);
+    property(
+        &mut content,
+        "available",
+        &package_info.available.to_string(),
+    );
+    property(&mut content, "broken", &package_info.broken.to_string());
+    if let Some(ref reason) = package_info.broken_reason {
+        property(&mut content, "broken-reason", reason);
+    }
+    if let Some(ref homepage) = package_info.homepage {
+        property(&mut content, "homepage", homepage);
+    }
+    if let Some(priority) = package_info.priority {
+        property(&mut content, "priority", &priority.to_string());
+    }
+    property(
+        &mut content,
+        "platforms",
+        &package_info.platforms.join(", "),
+    );
+    property(
+        &mut content,
+        "broken-on",
+        &package_info.bad_platforms.join(", "),
+    );
+    property(
+        &mut content,
+        "maintainers",
+        &package_info
+            .maintainers
+            .iter()
+            .map(|maintainer| maintainer.handle())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    property(&mut content, "drv-path", &package_info.drv_path);
+    content.push('\n');
+
+    let description = package_info
+        .long_description
+        .as_ref()
+        .or(package_info.description.as_ref());
+    section(
+        &mut content,
+        "Description",
+        &description.map_or(Vec::new(), |description| paragraphs(description)),
+    );
+
+    let mut links = Vec::new();
+    if let Some(ref download_page) = package_info.download_page {
+        links.push(format!("Download page: {}", download_page));
+    }
+    for (kind, url) in &package_info.repositories {
+        links.push(format!("Repository ({}): {}", kind, url));
+    }
+    if let Some(ref position) = package_info.position {
+        links.push(format!("Source position: `{}`", position));
+    }
+    for field in &package_info.extracted {
+        match &field.value {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(s) => links.push(format!("{}: {}", field.label, s)),
+            other => links.push(format!("{}: `{}`", field.label, other)),
+        }
+    }
+    section(&mut content, "Package Information", &links);
+
+    section(
+        &mut content,
+        "Maintainers",
+        &package_info
+            .maintainers
+            .iter()
+            .map(|maintainer| maintainer.markdown())
+            .collect::<Vec<_>>(),
+    );
+    section(
+        &mut content,
+        "Teams",
+        &package_info
+            .teams
+            .iter()
+            .map(|team| team.short_name.clone())
+            .collect::<Vec<_>>(),
+    );
+
+    let outputs: Vec<String> = package_info
+        .outputs
+        .iter()
+        .map(|output| {
+            let mut line = format!("`{}`", output);
+            if package_info.outputs_to_install.contains(output) {
+                line.push_str(" (installed by default)");
+            }
+            if let Some(path) = package_info.output_paths.get(output) {
+                line.push_str(&format!(": `{}`", path));
+            }
+            if let Some(size) = package_info
+                .output_sizes
+                .as_ref()
+                .and_then(|sizes| sizes.get(output))
+            {
+                line.push_str(&format!(", closure {}", format_size(*size)));
+            }
+            line
+        })
+        .collect();
+    section(&mut content, "Outputs", &outputs);
+
+    let sources: Vec<String> = package_info
+        .sources
+        .iter()
+        .filter_map(|source| {
+            let location = source.urls.first().or(source.store_path.as_ref())?;
+            Some(match source.hash {
+                Some(ref hash) => format!("{} (`{}`)", location, hash),
+                None => location.clone(),
+            })
+        })
+        .collect();
+    section(&mut content, "Source", &sources);
+
+    section(
+        &mut content,
+        "Dependencies",
+        &package_info
+            .dependencies
+            .iter()
+            .map(|dependency| format!("[[{}]]", drv_filename(dependency)))
+            .collect::<Vec<_>>(),
+    );
+    section(
+        &mut content,
+        "Input Sources",
+        &package_info
+            .input_srcs
+            .iter()
+            .map(|src| format!("`{}`", src))
+            .collect::<Vec<_>>(),
+    );
+
+    content.push_str(&format!(
+        "- *Generated on {}*\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+    content
+}
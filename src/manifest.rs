@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path};
+
+/// Files the last run generated, relative to the output directory, so
+/// `--clean` can remove exactly those. Template files, `packages.json` and
+/// `failed-cache.json` are not listed: they are meant to outlive a run.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    files: BTreeSet<String>,
+}
+
+pub fn path(outdir: &str) -> String {
+    format!("{}/manifest.json", outdir)
+}
+
+impl Manifest {
+    pub fn load(outdir: &str) -> Option<Self> {
+        let data = fs::read_to_string(path(outdir)).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Records `relative_path` if the run actually left a file there.
+    pub fn add_if_exists(&mut self, outdir: &str, relative_path: &str) {
+        if Path::new(outdir).join(relative_path).is_file() {
+            self.files.insert(relative_path.to_string());
+        }
+    }
+
+    /// Records every file below the directory `relative_dir`.
+    pub fn add_dir(&mut self, outdir: &str, relative_dir: &str) {
+        let Ok(entries) = fs::read_dir(Path::new(outdir).join(relative_dir)) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let relative_path = format!("{}/{}", relative_dir, entry.file_name().to_string_lossy());
+            if entry.path().is_dir() {
+                self.add_dir(outdir, &relative_path);
+            } else {
+                self.files.insert(relative_path);
+            }
+        }
+    }
+
+    pub fn extend(&mut self, files: impl IntoIterator<Item = String>) {
+        self.files.extend(files);
+    }
+
+    /// Removes the listed files and the directories they leave empty.
+    /// Entries that would point outside `outdir` are ignored. Returns how
+    /// many files were removed.
+    pub fn clean(&self, outdir: &str) -> usize {
+        let mut removed = 0;
+        for file in &self.files {
+            let relative = Path::new(file);
+            if !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                continue;
+            }
+            if fs::remove_file(Path::new(outdir).join(relative)).is_ok() {
+                removed += 1;
+            }
+            // Only succeeds for directories that are now empty
+            for parent in relative.ancestors().skip(1) {
+                if parent.as_os_str().is_empty()
+                    || fs::remove_dir(Path::new(outdir).join(parent)).is_err()
+                {
+                    break;
+                }
+            }
+        }
+        let _ = fs::remove_file(path(outdir));
+        removed
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{html, write_if_changed};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Folder of the output directory holding one page per chapter.
+pub const CHAPTERS_DIR: &str = "chapters";
+
+/// Chapter of the packages outside any package set, listed first.
+const TOP_LEVEL: &str = "top-level";
+
+/// A note as an mdBook page: without front matter, which mdBook would show
+/// as text, and with standard links, which it turns into links between
+/// pages.
+pub fn render_page(markdown: &str, relative_path: &str, extension: &str) -> String {
+    html::convert_wikilinks(
+        html::strip_front_matter(markdown),
+        &html::root_prefix(relative_path),
+        extension,
+    )
+}
+
+/// A link destination, in angle brackets in case the path has spaces.
+fn destination(path: &str) -> String {
+    format!("<{}>", path)
+}
+
+/// Link text with the brackets that would end it escaped.
+fn label(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+/// Writes `SUMMARY.md` with one chapter per top-level attribute set and
+/// the notes of its packages below it, the chapter pages, and a `book.toml`
+/// unless there is one already. `notes` maps attribute names to notes.
+/// Returns the files written, relative to `outdir`.
+pub fn write_book(outdir: &str, notes: &HashMap<String, String>) -> std::io::Result<Vec<String>> {
+    let mut chapters: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for (attribute, note) in notes {
+        let (chapter, name) = attribute
+            .split_once('.')
+            .unwrap_or((TOP_LEVEL, attribute.as_str()));
+        chapters.entry(chapter).or_default().push((name, note));
+    }
+    let top_level = chapters.remove_entry(TOP_LEVEL);
+
+    fs::create_dir_all(Path::new(outdir).join(CHAPTERS_DIR))?;
+    let mut written = Vec::new();
+    let mut summary = String::from("# Summary\n\n");
+    if Path::new(outdir).join("README.md").is_file() {
+        summary.push_str("[Introduction](README.md)\n\n");
+    }
+    for (chapter, mut packages) in top_level.into_iter().chain(chapters) {
+        packages.sort_by(|a, b| {
+            a.0.to_lowercase()
+                .cmp(&b.0.to_lowercase())
+                .then_with(|| a.0.cmp(b.0))
+        });
+        let title = if chapter == TOP_LEVEL {
+            "Top-level packages"
+        } else {
+            chapter
+        };
+        let page = format!("{}/{}.md", CHAPTERS_DIR, chapter);
+
+        let mut content = format!("# {}\n\n", title);
+        content.push_str(&format!("{} packages\n\n", packages.len()));
+        summary.push_str(&format!("- [{}]({})\n", label(title), destination(&page)));
+        for (name, note) in packages {
+            content.push_str(&format!(
+                "- [{}]({})\n",
+                label(name),
+                destination(&format!("../{}", note))
+            ));
+            summary.push_str(&format!("    - [{}]({})\n", label(name), destination(note)));
+        }
+        write_if_changed(&format!("{}/{}", outdir, page), &content)?;
+        written.push(page);
+    }
+    write_if_changed(&format!("{}/SUMMARY.md", outdir), &summary)?;
+    written.push("SUMMARY.md".to_string());
+
+    let book_toml = Path::new(outdir).join("book.toml");
+    if !book_toml.exists() {
+        fs::write(
+            book_toml,
+            "[book]\ntitle = \"Nixpkgs packages\"\nsrc = \".\"\n\n[build]\ncreate-missing = false\n",
+        )?;
+        written.push("book.toml".to_string());
+    }
+    Ok(written)
+}
@@ -0,0 +1,62 @@
+use crate::PackageInfo;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Bumped whenever the shape of a package record changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Packages written between flushes, so consumers tailing the file see
+/// progress without a syscall per line.
+pub const FLUSH_INTERVAL: usize = 100;
+
+#[derive(Serialize)]
+struct Header<'a> {
+    r#type: &'static str,
+    version: u32,
+    revision: &'a str,
+    git_url: &'a str,
+    generated_at: &'a str,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    r#type: &'static str,
+    #[serde(flatten)]
+    package: &'a PackageInfo,
+}
+
+/// Creates the stream and writes the header line, which is flushed right
+/// away so consumers can check the version before any records arrive.
+pub fn create(
+    path: &str,
+    revision: &str,
+    git_url: &str,
+    generated_at: &str,
+    count: usize,
+) -> std::io::Result<BufWriter<File>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let header = Header {
+        r#type: "header",
+        version: SCHEMA_VERSION,
+        revision,
+        git_url,
+        generated_at,
+        count,
+    };
+    serde_json::to_writer(&mut writer, &header)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// One package as a single line, including the trailing newline.
+pub fn record(package_info: &PackageInfo) -> serde_json::Result<String> {
+    let mut line = serde_json::to_string(&Record {
+        r#type: "package",
+        package: package_info,
+    })?;
+    line.push('\n');
+    Ok(line)
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Maps attribute names to the note written for them, so `--resume` can
+/// find a package's note without evaluating it for its drv path, and records
+/// packages that failed to evaluate so it doesn't retry them. Stored as
+/// `note-index.jsonl` and appended to as packages finish, so it survives a
+/// run that is killed halfway.
+pub struct NoteIndex {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    package: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// What an earlier run got done, by attribute name.
+#[derive(Default)]
+pub struct Checkpoint {
+    /// Note path of every indexed note that still exists
+    pub notes: HashMap<String, String>,
+    /// Error class of every package that failed to evaluate
+    pub failures: HashMap<String, String>,
+}
+
+pub fn path(outdir: &str) -> String {
+    format!("{}/note-index.jsonl", outdir)
+}
+
+/// Reads the index of `outdir`. Later lines win; unreadable lines, e.g. one
+/// cut off by a crash, are ignored.
+pub fn load(outdir: &str) -> Checkpoint {
+    let Ok(data) = fs::read_to_string(path(outdir)) else {
+        return Checkpoint::default();
+    };
+    let mut checkpoint = Checkpoint::default();
+    for line in data.lines() {
+        let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+            continue;
+        };
+        checkpoint.notes.remove(&entry.package);
+        checkpoint.failures.remove(&entry.package);
+        if let Some(note) = entry.note {
+            checkpoint.notes.insert(entry.package, note);
+        } else if let Some(error) = entry.error {
+            checkpoint.failures.insert(entry.package, error);
+        }
+    }
+    checkpoint
+        .notes
+        .retain(|_, note| Path::new(outdir).join(note).is_file());
+    checkpoint
+}
+
+impl NoteIndex {
+    /// Opens the index of `outdir`, keeping its entries when `append` is set
+    /// and starting over otherwise.
+    pub fn open(outdir: &str, append: bool) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path(outdir))?;
+        // A run killed mid-write may have left half a line behind
+        if append && !fs::read(path(outdir))?.ends_with(b"\n") {
+            file.write_all(b"\n")?;
+        }
+        Ok(NoteIndex {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, package: &str, note: &str) -> io::Result<()> {
+        self.append(Entry {
+            package: package.to_string(),
+            note: Some(note.to_string()),
+            error: None,
+        })
+    }
+
+    pub fn record_failure(&self, package: &str, error: &str) -> io::Result<()> {
+        self.append(Entry {
+            package: package.to_string(),
+            note: None,
+            error: Some(error.to_string()),
+        })
+    }
+
+    fn append(&self, entry: Entry) -> io::Result<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        // One unbuffered write per line keeps entries whole
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
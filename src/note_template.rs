@@ -0,0 +1,381 @@
+use anyhow::{bail, Context};
+use serde_json::Value;
+use std::fs;
+
+/// A note layout given with `--template`. `{{path}}` inserts a field of the
+/// package record, `{{#each path}}...{{/each}}` repeats its body for every
+/// item of a list or map and `{{#if path}}...{{/if}}` keeps its body only if
+/// the field is set and not empty. Both blocks take an `{{else}}` branch.
+pub struct NoteTemplate {
+    nodes: Vec<Node>,
+}
+
+enum Node {
+    Text(String),
+    Field(String),
+    Each {
+        path: String,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    If {
+        path: String,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// A block whose closing tag hasn't been reached yet.
+struct OpenBlock {
+    kind: String,
+    path: String,
+    line: usize,
+    body: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl OpenBlock {
+    fn nodes(&mut self) -> &mut Vec<Node> {
+        self.otherwise.as_mut().unwrap_or(&mut self.body)
+    }
+}
+
+/// The item an `{{#each}}` is at, innermost last.
+struct Scope<'a> {
+    value: &'a Value,
+    index: Option<usize>,
+    key: Option<&'a str>,
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+impl NoteTemplate {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read template at {}", path))?;
+        Self::parse(&source).with_context(|| format!("invalid template {}", path))
+    }
+
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut root = Vec::new();
+        let mut open: Vec<OpenBlock> = Vec::new();
+        let mut pos = 0;
+
+        while let Some(found) = source[pos..].find("{{") {
+            let start = pos + found;
+            let Some(length) = source[start..].find("}}") else {
+                bail!("unclosed `{{{{` on line {}", line_of(source, start));
+            };
+            let end = start + length + 2;
+            let tag = source[start + 2..end - 2].trim();
+            let line = line_of(source, start);
+
+            // A block tag alone on its line takes the line with it, so
+            // blocks don't leave blank lines behind
+            let mut text_end = start;
+            let mut next = end;
+            if tag.starts_with(['#', '/']) || tag == "else" {
+                let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+                let line_end = source[end..]
+                    .find('\n')
+                    .map_or(source.len(), |i| end + i + 1);
+                if source[line_start..start].trim().is_empty()
+                    && source[end..line_end].trim().is_empty()
+                {
+                    text_end = line_start.max(pos);
+                    next = line_end;
+                }
+            }
+
+            let nodes = open.last_mut().map_or(&mut root, OpenBlock::nodes);
+            if text_end > pos {
+                nodes.push(Node::Text(source[pos..text_end].to_string()));
+            }
+
+            if let Some(block) = tag.strip_prefix('#') {
+                let (kind, path) = block.split_once(' ').unwrap_or((block, ""));
+                if !matches!(kind, "each" | "if") {
+                    bail!("unknown block `{{{{#{}}}}}` on line {}", kind, line);
+                }
+                if path.trim().is_empty() {
+                    bail!("`{{{{#{}}}}}` without a field on line {}", kind, line);
+                }
+                open.push(OpenBlock {
+                    kind: kind.to_string(),
+                    path: path.trim().to_string(),
+                    line,
+                    body: Vec::new(),
+                    otherwise: None,
+                });
+            } else if tag == "else" {
+                match open.last_mut() {
+                    Some(block) if block.otherwise.is_none() => block.otherwise = Some(Vec::new()),
+                    Some(_) => bail!("second `{{{{else}}}}` in one block on line {}", line),
+                    None => bail!("`{{{{else}}}}` outside of a block on line {}", line),
+                }
+            } else if let Some(kind) = tag.strip_prefix('/') {
+                let Some(block) = open.pop() else {
+                    bail!("`{{{{/{}}}}}` without an open block on line {}", kind, line);
+                };
+                if kind != block.kind {
+                    bail!(
+                        "`{{{{/{}}}}}` on line {} closes `{{{{#{}}}}}` from line {}",
+                        kind,
+                        line,
+                        block.kind,
+                        block.line
+                    );
+                }
+                let node = match block.kind.as_str() {
+                    "each" => Node::Each {
+                        path: block.path,
+                        body: block.body,
+                        otherwise: block.otherwise.unwrap_or_default(),
+                    },
+                    _ => Node::If {
+                        path: block.path,
+                        body: block.body,
+                        otherwise: block.otherwise.unwrap_or_default(),
+                    },
+                };
+                open.last_mut()
+                    .map_or(&mut root, OpenBlock::nodes)
+                    .push(node);
+            } else if tag.is_empty() {
+                bail!("empty `{{{{}}}}` on line {}", line);
+            } else {
+                nodes.push(Node::Field(tag.to_string()));
+            }
+            pos = next;
+        }
+
+        if let Some(block) = open.last() {
+            bail!(
+                "`{{{{#{} {}}}}}` from line {} is never closed",
+                block.kind,
+                block.path,
+                block.line
+            );
+        }
+        if pos < source.len() {
+            root.push(Node::Text(source[pos..].to_string()));
+        }
+        Ok(NoteTemplate { nodes: root })
+    }
+
+    pub fn render(&self, context: &Value) -> String {
+        let mut out = String::new();
+        let mut scopes = vec![Scope {
+            value: context,
+            index: None,
+            key: None,
+        }];
+        render_nodes(&self.nodes, &mut scopes, &mut out);
+        out
+    }
+}
+
+fn render_nodes<'a>(nodes: &[Node], scopes: &mut Vec<Scope<'a>>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field(path) => {
+                let scope = scopes.last().expect("the package is always in scope");
+                match path.as_str() {
+                    "@index" => out.push_str(&scope.index.map_or(String::new(), |i| i.to_string())),
+                    "@key" => out.push_str(scope.key.unwrap_or_default()),
+                    _ => out.push_str(&display(lookup(path, scopes))),
+                }
+            }
+            Node::Each {
+                path,
+                body,
+                otherwise,
+            } => {
+                let items: Vec<Scope> = match lookup(path, scopes) {
+                    Some(Value::Array(items)) => items
+                        .iter()
+                        .enumerate()
+                        .map(|(index, value)| Scope {
+                            value,
+                            index: Some(index),
+                            key: None,
+                        })
+                        .collect(),
+                    Some(Value::Object(items)) => items
+                        .iter()
+                        .enumerate()
+                        .map(|(index, (key, value))| Scope {
+                            value,
+                            index: Some(index),
+                            key: Some(key),
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                if items.is_empty() {
+                    render_nodes(otherwise, scopes, out);
+                }
+                for item in items {
+                    scopes.push(item);
+                    render_nodes(body, scopes, out);
+                    scopes.pop();
+                }
+            }
+            Node::If {
+                path,
+                body,
+                otherwise,
+            } => {
+                if is_set(lookup(path, scopes)) {
+                    render_nodes(body, scopes, out);
+                } else {
+                    render_nodes(otherwise, scopes, out);
+                }
+            }
+        }
+    }
+}
+
+/// Resolves a dotted path, e.g. `maintainers.0.github`. `this` is the
+/// current item; any other first segment is looked up in the current item,
+/// then in the enclosing ones and finally in the package.
+fn lookup<'a>(path: &str, scopes: &[Scope<'a>]) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let first = segments.next()?;
+    let mut value = match first {
+        "this" => scopes.last()?.value,
+        _ => scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.value.get(first))?,
+    };
+    for segment in segments {
+        value = match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+fn is_set(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::Object(items)) => !items.is_empty(),
+        Some(_) => true,
+    }
+}
+
+/// Text for a field: strings as they are, lists comma separated, missing
+/// fields and `null` as nothing.
+fn display(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| display(Some(item)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(value) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(source: &str, context: &Value) -> String {
+        NoteTemplate::parse(source).unwrap().render(context)
+    }
+
+    const MAINTAINERS: &str = "# {{name}}\n\
+        {{#each maintainers}}\n\
+        - {{@index}}: {{github}} of {{name}}\n\
+        {{else}}\n\
+        No maintainers\n\
+        {{/each}}\n\
+        {{#if dependency_links}}\n\
+        Depends on {{dependency_links}}\n\
+        {{/if}}\n";
+
+    #[test]
+    fn blocks_render_each_item_or_their_else_branch() {
+        let package = json!({
+            "name": "hello",
+            "maintainers": [{ "github": "alice" }, { "github": "bob" }],
+            "dependency_links": ["[[glibc]]", "[[bash]]"]
+        });
+        assert_eq!(
+            render(MAINTAINERS, &package),
+            "# hello\n- 0: alice of hello\n- 1: bob of hello\nDepends on [[glibc]], [[bash]]\n"
+        );
+
+        // Empty lists, like a package without maintainers or dependencies
+        let package = json!({ "name": "hello", "maintainers": [], "dependency_links": [] });
+        assert_eq!(render(MAINTAINERS, &package), "# hello\nNo maintainers\n");
+        let package = json!({ "name": "hello" });
+        assert_eq!(render(MAINTAINERS, &package), "# hello\nNo maintainers\n");
+    }
+
+    #[test]
+    fn fields_resolve_dotted_paths_and_map_keys() {
+        let package = json!({
+            "maintainers": [{ "github": "alice" }],
+            "output_paths": { "out": "/nix/store/a-hello", "man": "/nix/store/b-hello-man" },
+            "broken": false,
+            "priority": null
+        });
+        assert_eq!(render("{{maintainers.0.github}}", &package), "alice");
+        assert_eq!(
+            render("{{maintainers.1.github}}{{missing.field}}", &package),
+            ""
+        );
+        assert_eq!(
+            render(
+                "{{#each output_paths}}{{@key}}={{this}} {{/each}}",
+                &package
+            ),
+            "man=/nix/store/b-hello-man out=/nix/store/a-hello "
+        );
+        assert_eq!(
+            render("{{broken}} {{#if broken}}yes{{else}}no{{/if}}", &package),
+            "false no"
+        );
+        assert_eq!(render("[{{priority}}]", &package), "[]");
+    }
+
+    #[test]
+    fn malformed_templates_name_the_line() {
+        let error = |source: &str| format!("{}", NoteTemplate::parse(source).err().unwrap());
+        assert_eq!(error("a\n{{name"), "unclosed `{{` on line 2");
+        assert_eq!(
+            error("{{#with x}}{{/with}}"),
+            "unknown block `{{#with}}` on line 1"
+        );
+        assert_eq!(
+            error("{{#each}}{{/each}}"),
+            "`{{#each}}` without a field on line 1"
+        );
+        assert_eq!(
+            error("{{#if a}}\n{{/each}}"),
+            "`{{/each}}` on line 2 closes `{{#if}}` from line 1"
+        );
+        assert_eq!(
+            error("{{#if a}}{{else}}{{else}}{{/if}}"),
+            "second `{{else}}` in one block on line 1"
+        );
+        assert_eq!(error("{{else}}"), "`{{else}}` outside of a block on line 1");
+        assert_eq!(
+            error("\n\n{{#each maintainers}}"),
+            "`{{#each maintainers}}` from line 3 is never closed"
+        );
+        assert_eq!(error("{{ }}"), "empty `{{}}` on line 1");
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Reorders items finished out of order by parallel workers so they reach
+/// the writer in index order.
+///
+/// Every index in `0..n` must be submitted exactly once (with `None` for
+/// items that produced nothing), otherwise later items are never written.
+/// Items go over a channel of `bound` slots to a thread of its own, which
+/// holds back those that arrive early and writes the rest. Workers only
+/// wait while that channel is full, and the writer thread empties it
+/// whatever the order of the items, so a rayon worker never waits on
+/// another worker's item.
+pub struct OrderedSink<W> {
+    sender: SyncSender<(usize, Option<String>)>,
+    writer: JoinHandle<io::Result<W>>,
+}
+
+impl<W: Write + Send + 'static> OrderedSink<W> {
+    /// Flushes the writer after every `flush_interval` written items, or
+    /// with 0 only in `finish`; the former is for outputs that are read
+    /// while the run is going.
+    pub fn new(writer: W, bound: usize, flush_interval: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(bound.max(1));
+        let writer = thread::spawn(move || {
+            let mut writer = writer;
+            let mut next = 0;
+            let mut written = 0usize;
+            let mut pending: BTreeMap<usize, Option<String>> = BTreeMap::new();
+            let mut error = None;
+            for (index, item) in receiver {
+                pending.insert(index, item);
+                while let Some(item) = pending.remove(&next) {
+                    next += 1;
+                    // After a failed write the rest is dropped; the error is
+                    // reported by `finish`
+                    let (Some(item), None) = (item, &error) else {
+                        continue;
+                    };
+                    written += 1;
+                    let flush = flush_interval > 0 && written.is_multiple_of(flush_interval);
+                    let result = writer.write_all(item.as_bytes()).and_then(|_| {
+                        if flush {
+                            writer.flush()
+                        } else {
+                            Ok(())
+                        }
+                    });
+                    if let Err(e) = result {
+                        error = Some(e);
+                    }
+                }
+            }
+            match error {
+                Some(e) => Err(e),
+                None => writer.flush().map(|()| writer),
+            }
+        });
+        OrderedSink { sender, writer }
+    }
+
+    pub fn submit(&self, index: usize, item: Option<String>) {
+        // The writer thread only stops once the sink is finished
+        let _ = self.sender.send((index, item));
+    }
+
+    /// Waits for everything submitted to be written, then returns the
+    /// flushed writer, or the first write error.
+    pub fn finish(self) -> io::Result<W> {
+        drop(self.sender);
+        self.writer
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn workers_far_ahead_do_not_stall_the_pool() {
+        // Far more items in flight than the channel holds
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let sink = OrderedSink::new(Vec::new(), 2, 0);
+        let indices: Vec<usize> = (0..4096).collect();
+        pool.install(|| {
+            indices
+                .par_chunks(64)
+                .enumerate()
+                .for_each(|(chunk_index, chunk)| {
+                    for (offset, index) in chunk.iter().enumerate() {
+                        assert_eq!(chunk_index * 64 + offset, *index);
+                        let item = (index % 3 != 0).then(|| format!("{}\n", index));
+                        sink.submit(*index, item);
+                    }
+                });
+        });
+
+        let written = String::from_utf8(sink.finish().unwrap()).unwrap();
+        let expected: String = (0..4096)
+            .filter(|index| index % 3 != 0)
+            .map(|index| format!("{}\n", index))
+            .collect();
+        assert_eq!(written, expected);
+    }
+}
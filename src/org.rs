@@ -0,0 +1,231 @@
+use crate::{drv_filename, format_size, graph, PackageInfo};
+use chrono::Utc;
+
+/// Org keywords and drawer properties end at the line break.
+fn single_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `[[target][label]]`, with the brackets org would end the link at left
+/// out of the label.
+fn link(target: &str, label: &str) -> String {
+    let label: String = label.chars().filter(|c| !matches!(c, '[' | ']')).collect();
+    format!("[[{}][{}]]", target, label)
+}
+
+/// `=verbatim=` text. Org has no escape for `=`, so text containing one is
+/// left as is.
+fn verbatim(text: &str) -> String {
+    if text.contains('=') {
+        text.to_string()
+    } else {
+        format!("={}=", text)
+    }
+}
+
+/// Renders a package as an org file: a property drawer with the metadata,
+/// `#+TITLE` and friends, then one heading per section. Dependencies
+/// link to the files of their notes next to this one.
+pub fn render_page(package_info: &PackageInfo, extension: &str) -> String {
+    let mut content = String::new();
+
+    // A file-level property drawer has to come first
+    content.push_str(":PROPERTIES:\n");
+    let mut property = |key: &str, value: &str| {
+        let value = single_line(value);
+        if !value.is_empty() {
+            content.push_str(&format!(":{}: {}\n", key, value));
+        }
+    };
+    property("NAME", &package_info.name);
+    property("ATTRIBUTE", &package_info.attribute);
+    property("VERSION", &package_info.version);
+    property("LICENSE", &package_info.license_short_name);
+    property("AVAILABLE", &package_info.available.to_string());
+    property("BROKEN", &package_info.broken.to_string());
+    if let Some(ref reason) = package_info.broken_reason {
+        property("BROKEN_REASON", reason);
+    }
+    if let Some(ref homepage) = package_info.homepage {
+        property("HOMEPAGE", homepage);
+    }
+    if let Some(priority) = package_info.priority {
+        property("PRIORITY", &priority.to_string());
+    }
+    property("PLATFORMS", &package_info.platforms.join(" "));
+    property("BROKEN_ON", &package_info.bad_platforms.join(" "));
+    property(
+        "MAINTAINERS",
+        &package_info
+            .maintainers
+            .iter()
+            .map(|maintainer| maintainer.handle())
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    property("DRV_PATH", &package_info.drv_path);
+    content.push_str(":END:\n");
+    content.push_str(&format!("#+TITLE: {}\n", single_line(&package_info.name)));
+    if let Some(ref description) = package_info.description {
+        content.push_str(&format!("#+SUBTITLE: {}\n", single_line(description)));
+    }
+    let mut tags = Vec::new();
+    if package_info.broken {
+        tags.push("broken".to_string());
+    }
+    if !package_info.available {
+        tags.push("not_available".to_string());
+    }
+    // Org tags are made of letters, digits, `_` and `@`
+    tags.extend(
+        package_info
+            .teams
+            .iter()
+            .map(|team| format!("team_{}", team.short_name.replace(['-', ' '], "_"))),
+    );
+    if !tags.is_empty() {
+        content.push_str(&format!("#+FILETAGS: :{}:\n", tags.join(":")));
+    }
+    content.push('\n');
+
+    let description = package_info
+        .long_description
+        .as_ref()
+        .or(package_info.description.as_ref());
+    if let Some(description) = description {
+        content.push_str("* Description\n\n");
+        content.push_str(description.trim_end());
+        content.push_str("\n\n");
+    }
+
+    content.push_str("* Package Information\n\n");
+    if let Some(ref homepage) = package_info.homepage {
+        content.push_str(&format!("- Homepage :: {}\n", link(homepage, homepage)));
+    }
+    if let Some(ref download_page) = package_info.download_page {
+        content.push_str(&format!(
+            "- Download page :: {}\n",
+            link(download_page, download_page)
+        ));
+    }
+    for (kind, url) in &package_info.repositories {
+        if kind == "repository" {
+            content.push_str(&format!("- Repository :: {}\n", link(url, url)));
+        } else {
+            content.push_str(&format!("- Repository ({}) :: {}\n", kind, link(url, url)));
+        }
+    }
+    if let Some(ref position) = package_info.position {
+        content.push_str(&format!("- Source position :: {}\n", verbatim(position)));
+    }
+    for field in &package_info.extracted {
+        match &field.value {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(s) => {
+                content.push_str(&format!("- {} :: {}\n", field.label, single_line(s)))
+            }
+            other => content.push_str(&format!(
+                "- {} :: {}\n",
+                field.label,
+                verbatim(&other.to_string())
+            )),
+        }
+    }
+    content.push('\n');
+
+    if !package_info.maintainers.is_empty() {
+        content.push_str("* Maintainers\n\n");
+        for maintainer in &package_info.maintainers {
+            let github = maintainer.github.as_ref().map(|github| {
+                link(
+                    &format!("https://github.com/{}", github),
+                    &format!("@{}", github),
+                )
+            });
+            let details: Vec<String> = github.into_iter().chain(maintainer.email.clone()).collect();
+            match (&maintainer.name, details.is_empty()) {
+                (Some(name), false) => {
+                    content.push_str(&format!("- {} ({})\n", name, details.join(", ")))
+                }
+                (None, false) => content.push_str(&format!("- {}\n", details.join(", "))),
+                (_, true) => content.push_str(&format!("- {}\n", maintainer.display())),
+            }
+        }
+        content.push('\n');
+    }
+
+    if !package_info.teams.is_empty() {
+        content.push_str("* Teams\n\n");
+        for team in &package_info.teams {
+            content.push_str(&format!("- {}\n", team.short_name));
+        }
+        content.push('\n');
+    }
+
+    if !package_info.outputs.is_empty() {
+        content.push_str("* Outputs\n\n");
+        for output in &package_info.outputs {
+            let mut line = format!("- {}", verbatim(output));
+            if package_info.outputs_to_install.contains(output) {
+                line.push_str(" (installed by default)");
+            }
+            if let Some(path) = package_info.output_paths.get(output) {
+                line.push_str(&format!(" :: {}", verbatim(path)));
+            }
+            if let Some(size) = package_info
+                .output_sizes
+                .as_ref()
+                .and_then(|sizes| sizes.get(output))
+            {
+                line.push_str(&format!(", closure {}", format_size(*size)));
+            }
+            content.push_str(&line);
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+
+    if !package_info.sources.is_empty() {
+        content.push_str("* Source\n\n");
+        for source in &package_info.sources {
+            for url in &source.urls {
+                content.push_str(&format!("- URL :: {}\n", link(url, url)));
+            }
+            if let Some(ref hash) = source.hash {
+                content.push_str(&format!("- Hash :: {}\n", verbatim(hash)));
+            }
+            if let Some(ref path) = source.store_path {
+                content.push_str(&format!("- Store path :: {}\n", verbatim(path)));
+            }
+        }
+        content.push('\n');
+    }
+
+    if !package_info.dependencies.is_empty() {
+        content.push_str("* Dependencies\n\n");
+        for dependency in &package_info.dependencies {
+            content.push_str(&format!(
+                "- {}\n",
+                link(
+                    &format!("file:{}.{}", drv_filename(dependency), extension),
+                    graph::store_path_name(dependency)
+                )
+            ));
+        }
+        content.push('\n');
+    }
+
+    if !package_info.input_srcs.is_empty() {
+        content.push_str("* Input Sources\n\n");
+        for src in &package_info.input_srcs {
+            content.push_str(&format!("- {}\n", verbatim(src)));
+        }
+        content.push('\n');
+    }
+
+    content.push_str(&format!(
+        "/Generated on {}/\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+    content
+}
@@ -0,0 +1,270 @@
+use crate::export::CachedPackage;
+use anyhow::anyhow;
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DataType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+
+const PACKAGES_SCHEMA: &str = "
+message package {
+    required binary attribute (STRING);
+    required binary name (STRING);
+    required binary version (STRING);
+    optional binary description (STRING);
+    optional binary homepage (STRING);
+    required binary license (STRING);
+    required boolean available;
+    required boolean broken;
+    optional binary broken_reason (STRING);
+    optional int64 priority;
+    optional binary position (STRING);
+    optional binary drv_path (STRING);
+    optional int64 dependency_count;
+    required group maintainers (LIST) {
+        repeated group list {
+            required binary element (STRING);
+        }
+    }
+    required group platforms (LIST) {
+        repeated group list {
+            required binary element (STRING);
+        }
+    }
+}
+";
+
+const DEPENDENCIES_SCHEMA: &str = "
+message dependency {
+    required binary attribute (STRING);
+    required binary drv_path (STRING);
+    required binary dependency (STRING);
+    optional binary dependency_attribute (STRING);
+}
+";
+
+/// Rows per row group, so readers can skip and parallelize over them.
+const ROW_GROUP_SIZE: usize = 65536;
+
+/// Values of one column of a row group, with the levels Parquet stores for
+/// optional and list columns.
+struct Column<T> {
+    values: Vec<T>,
+    def_levels: Vec<i16>,
+    rep_levels: Vec<i16>,
+}
+
+impl<T> Column<T> {
+    fn new() -> Self {
+        Column {
+            values: Vec::new(),
+            def_levels: Vec::new(),
+            rep_levels: Vec::new(),
+        }
+    }
+
+    fn required(&mut self, value: T) {
+        self.values.push(value);
+    }
+
+    fn optional(&mut self, value: Option<T>) {
+        match value {
+            Some(value) => {
+                self.values.push(value);
+                self.def_levels.push(1);
+            }
+            None => self.def_levels.push(0),
+        }
+    }
+
+    /// A list of values; an empty list is a single level without a value.
+    fn list(&mut self, values: impl IntoIterator<Item = T>) {
+        let start = self.def_levels.len();
+        for value in values {
+            let rep_level = if self.def_levels.len() == start { 0 } else { 1 };
+            self.values.push(value);
+            self.def_levels.push(1);
+            self.rep_levels.push(rep_level);
+        }
+        if self.def_levels.len() == start {
+            self.def_levels.push(0);
+            self.rep_levels.push(0);
+        }
+    }
+}
+
+fn text(value: &str) -> ByteArray {
+    ByteArray::from(value)
+}
+
+/// Levels of a column, `None` for the levels a column has no use for.
+fn levels(levels: &[i16]) -> Option<&[i16]> {
+    (!levels.is_empty()).then_some(levels)
+}
+
+/// Writes the next column of `row_group`, which must be of type `D`.
+fn write_column<D: DataType>(
+    row_group: &mut SerializedRowGroupWriter<File>,
+    column: &Column<D::T>,
+) -> anyhow::Result<()> {
+    let mut writer = row_group
+        .next_column()?
+        .ok_or_else(|| anyhow!("more columns than the schema has"))?;
+    writer.typed::<D>().write_batch(
+        &column.values,
+        levels(&column.def_levels),
+        levels(&column.rep_levels),
+    )?;
+    writer.close()?;
+    Ok(())
+}
+
+fn file_writer(path: &Path, schema: &str) -> anyhow::Result<SerializedFileWriter<File>> {
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_created_by(format!("nixpkgs-vault {}", env!("CARGO_PKG_VERSION")))
+        .build();
+    Ok(SerializedFileWriter::new(
+        File::create(path)?,
+        Arc::new(parse_message_type(schema)?),
+        Arc::new(properties),
+    )?)
+}
+
+fn write_packages(path: &Path, packages: &[CachedPackage]) -> anyhow::Result<()> {
+    let mut writer = file_writer(path, PACKAGES_SCHEMA)?;
+    for chunk in packages.chunks(ROW_GROUP_SIZE) {
+        let mut attribute = Column::new();
+        let mut name = Column::new();
+        let mut version = Column::new();
+        let mut description = Column::new();
+        let mut homepage = Column::new();
+        let mut license = Column::new();
+        let mut available = Column::new();
+        let mut broken = Column::new();
+        let mut broken_reason = Column::new();
+        let mut priority = Column::new();
+        let mut position = Column::new();
+        let mut drv_path = Column::new();
+        let mut dependency_count = Column::new();
+        let mut maintainers = Column::new();
+        let mut platforms = Column::new();
+        for CachedPackage {
+            package_info,
+            has_derivation,
+            ..
+        } in chunk
+        {
+            attribute.required(text(&package_info.attribute));
+            name.required(text(&package_info.name));
+            version.required(text(&package_info.version));
+            description.optional(package_info.description.as_deref().map(text));
+            homepage.optional(package_info.homepage.as_deref().map(text));
+            license.required(text(&package_info.license_short_name));
+            available.required(package_info.available);
+            broken.required(package_info.broken);
+            broken_reason.optional(package_info.broken_reason.as_deref().map(text));
+            priority.optional(package_info.priority);
+            position.optional(package_info.position.as_deref().map(text));
+            drv_path.optional(has_derivation.then(|| text(&package_info.drv_path)));
+            dependency_count
+                .optional(has_derivation.then_some(package_info.dependencies.len() as i64));
+            maintainers.list(
+                package_info
+                    .maintainers
+                    .iter()
+                    .map(|maintainer| text(&maintainer.handle())),
+            );
+            platforms.list(package_info.platforms.iter().map(|platform| text(platform)));
+        }
+
+        let mut row_group = writer.next_row_group()?;
+        write_column::<ByteArrayType>(&mut row_group, &attribute)?;
+        write_column::<ByteArrayType>(&mut row_group, &name)?;
+        write_column::<ByteArrayType>(&mut row_group, &version)?;
+        write_column::<ByteArrayType>(&mut row_group, &description)?;
+        write_column::<ByteArrayType>(&mut row_group, &homepage)?;
+        write_column::<ByteArrayType>(&mut row_group, &license)?;
+        write_column::<BoolType>(&mut row_group, &available)?;
+        write_column::<BoolType>(&mut row_group, &broken)?;
+        write_column::<ByteArrayType>(&mut row_group, &broken_reason)?;
+        write_column::<Int64Type>(&mut row_group, &priority)?;
+        write_column::<ByteArrayType>(&mut row_group, &position)?;
+        write_column::<ByteArrayType>(&mut row_group, &drv_path)?;
+        write_column::<Int64Type>(&mut row_group, &dependency_count)?;
+        write_column::<ByteArrayType>(&mut row_group, &maintainers)?;
+        write_column::<ByteArrayType>(&mut row_group, &platforms)?;
+        row_group.close()?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// One row per dependency of a package with a cached derivation, with the
+/// attribute of the dependency if it is a package of packages.json too.
+fn write_dependencies(path: &Path, packages: &[CachedPackage]) -> anyhow::Result<()> {
+    let evaluated: Vec<&CachedPackage> = packages
+        .iter()
+        .filter(|package| package.has_derivation)
+        .collect();
+    let attributes: HashMap<&str, &str> = evaluated
+        .iter()
+        .map(|package| {
+            (
+                package.package_info.drv_path.as_str(),
+                package.package_info.attribute.as_str(),
+            )
+        })
+        .collect();
+    let edges: Vec<(&CachedPackage, &str)> = evaluated
+        .iter()
+        .flat_map(|package| {
+            package
+                .package_info
+                .dependencies
+                .iter()
+                .map(move |dependency| (*package, dependency.as_str()))
+        })
+        .collect();
+
+    let mut writer = file_writer(path, DEPENDENCIES_SCHEMA)?;
+    for chunk in edges.chunks(ROW_GROUP_SIZE) {
+        let mut attribute = Column::new();
+        let mut drv_path = Column::new();
+        let mut dependency = Column::new();
+        let mut dependency_attribute = Column::new();
+        for (package, drv) in chunk {
+            attribute.required(text(&package.package_info.attribute));
+            drv_path.required(text(&package.package_info.drv_path));
+            dependency.required(text(drv));
+            dependency_attribute.optional(attributes.get(drv).copied().map(text));
+        }
+
+        let mut row_group = writer.next_row_group()?;
+        write_column::<ByteArrayType>(&mut row_group, &attribute)?;
+        write_column::<ByteArrayType>(&mut row_group, &drv_path)?;
+        write_column::<ByteArrayType>(&mut row_group, &dependency)?;
+        write_column::<ByteArrayType>(&mut row_group, &dependency_attribute)?;
+        row_group.close()?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes `packages.parquet` and `dependencies.parquet` to `dir`, returning
+/// their paths.
+pub fn write(dir: &str, packages: &[CachedPackage]) -> anyhow::Result<[String; 2]> {
+    fs::create_dir_all(dir)?;
+    let packages_path = Path::new(dir).join("packages.parquet");
+    let dependencies_path = Path::new(dir).join("dependencies.parquet");
+    write_packages(&packages_path, packages)?;
+    write_dependencies(&dependencies_path, packages)?;
+    Ok([
+        packages_path.display().to_string(),
+        dependencies_path.display().to_string(),
+    ])
+}
@@ -0,0 +1,97 @@
+use colored::*;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Wall-clock timings of a run, printed and added to the summary with
+/// `--profile`.
+#[derive(Default)]
+pub struct Profiler {
+    phases: Vec<PhaseTiming>,
+    evaluations: Mutex<Vec<Duration>>,
+}
+
+#[derive(Serialize)]
+struct PhaseTiming {
+    phase: &'static str,
+    secs: f64,
+}
+
+#[derive(Serialize)]
+pub struct ProfileReport {
+    phases: Vec<PhaseTiming>,
+    /// `None` when no package was evaluated
+    evaluation: Option<EvaluationStats>,
+}
+
+/// Distribution of the per-package `nix derivation show` times.
+#[derive(Serialize)]
+struct EvaluationStats {
+    count: usize,
+    min_secs: f64,
+    median_secs: f64,
+    p99_secs: f64,
+    max_secs: f64,
+}
+
+impl Profiler {
+    /// Records a phase that started at `start` and just finished.
+    pub fn phase(&mut self, phase: &'static str, start: Instant) {
+        self.phases.push(PhaseTiming {
+            phase,
+            secs: start.elapsed().as_secs_f64(),
+        });
+    }
+
+    /// Records the evaluation time of one package; called from workers.
+    pub fn record_evaluation(&self, elapsed: Duration) {
+        self.evaluations.lock().unwrap().push(elapsed);
+    }
+
+    pub fn report(self) -> ProfileReport {
+        let mut evaluations = self.evaluations.into_inner().unwrap();
+        evaluations.sort();
+        // Nearest-rank percentile over the sorted samples
+        let percentile = |p: usize| {
+            let rank = (evaluations.len() * p).div_ceil(100).max(1);
+            evaluations[rank - 1].as_secs_f64()
+        };
+        let evaluation = (!evaluations.is_empty()).then(|| EvaluationStats {
+            count: evaluations.len(),
+            min_secs: evaluations[0].as_secs_f64(),
+            median_secs: percentile(50),
+            p99_secs: percentile(99),
+            max_secs: evaluations[evaluations.len() - 1].as_secs_f64(),
+        });
+        ProfileReport {
+            phases: self.phases,
+            evaluation,
+        }
+    }
+}
+
+impl ProfileReport {
+    pub fn print(&self) {
+        println!("{}", "⏱️  Profile:".cyan().bold());
+        let row = |label: &str, value: String| {
+            println!("  {:<18} {}", label.cyan(), value.bright_white());
+        };
+        for phase in &self.phases {
+            row(phase.phase, format!("{:.3}s", phase.secs));
+        }
+        if let Some(ref evaluation) = self.evaluation {
+            row("evaluations", evaluation.count.to_string());
+            row(
+                "eval min/median",
+                format!(
+                    "{:.3}s / {:.3}s",
+                    evaluation.min_secs, evaluation.median_secs
+                ),
+            );
+            row(
+                "eval p99/max",
+                format!("{:.3}s / {:.3}s", evaluation.p99_secs, evaluation.max_secs),
+            );
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    /// A progress bar on the terminal
+    Bar,
+    /// Newline-delimited JSON events for other programs
+    Json,
+}
+
+/// One line of `--progress-format json`, tagged by `event`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Started {
+        total: usize,
+        revision: &'a str,
+        outdir: &'a str,
+    },
+    PackageDone {
+        package: &'a str,
+        done: usize,
+        total: usize,
+    },
+    PackageFailed {
+        package: &'a str,
+        error: &'a str,
+        done: usize,
+        total: usize,
+    },
+    Finished {
+        processed: usize,
+        errors: usize,
+        elapsed_secs: f64,
+    },
+}
+
+/// Writes progress events to stdout or to `--progress-output`, which may be
+/// a named pipe.
+pub struct JsonProgress {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonProgress {
+    pub fn open(path: Option<&str>) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = match path {
+            // Not truncating keeps this working for pipes
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(JsonProgress {
+            out: Mutex::new(out),
+        })
+    }
+
+    /// Writes and flushes one event. A reader that went away doesn't stop
+    /// the run, so write errors are ignored.
+    pub fn emit(&self, event: &Event) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+        let mut out = self.out.lock().unwrap();
+        let _ = out.write_all(line.as_bytes()).and_then(|_| out.flush());
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(clap::Args, Debug)]
+pub struct CleanArgs {
+    /// Only remove notes of packages no longer in packages.json, instead of the last run's files
+    #[arg(long)]
+    stale: bool,
+
+    /// Move the stale notes to archive/ instead of deleting them
+    #[arg(long, requires = "stale")]
+    archive: bool,
+}
+
+impl CleanArgs {
+    pub fn stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn archive(&self) -> bool {
+        self.archive
+    }
+}
+
+/// Folder of the output directory that `--archive` moves pruned notes to,
+/// keeping their path below the notes folder.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// `packages/python3Packages/requests.md` -> `python3Packages/requests`,
+/// for notes in `notes_dir`. Saved derivations share the stem of their note.
+pub fn stem<'a>(notes_dir: &str, relative_path: &'a str) -> &'a str {
+    let path = relative_path
+        .strip_prefix(notes_dir)
+        .and_then(|path| path.strip_prefix('/'))
+        .unwrap_or(relative_path);
+    if let Some(stem) = path.strip_suffix(".drv.json") {
+        return stem;
+    }
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => &path[..dot],
+        _ => path,
+    }
+}
+
+/// Removes every file below `notes_dir` whose stem `is_stale` picks, or
+/// moves it to `archive/` with `archive`, and the directories that leaves
+/// empty. Returns the pruned paths, relative to `outdir`.
+pub fn prune(
+    outdir: &str,
+    notes_dir: &str,
+    archive: bool,
+    is_stale: impl Fn(&str) -> bool,
+) -> io::Result<Vec<String>> {
+    let mut notes = Vec::new();
+    collect_files(Path::new(outdir), notes_dir, &mut notes)?;
+    notes.sort();
+
+    let mut pruned = Vec::new();
+    for note in notes {
+        let stem = stem(notes_dir, &note);
+        if !is_stale(stem) {
+            continue;
+        }
+        let source = Path::new(outdir).join(&note);
+        if archive {
+            let target = Path::new(outdir)
+                .join(ARCHIVE_DIR)
+                .join(&note[notes_dir.len() + 1..]);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&source, &target)?;
+        } else {
+            fs::remove_file(&source)?;
+        }
+        // Only succeeds for directories that are now empty
+        for parent in Path::new(&note).ancestors().skip(1) {
+            if parent == Path::new(notes_dir)
+                || parent.as_os_str().is_empty()
+                || fs::remove_dir(Path::new(outdir).join(parent)).is_err()
+            {
+                break;
+            }
+        }
+        pruned.push(note);
+    }
+    Ok(pruned)
+}
+
+fn collect_files(outdir: &Path, relative_dir: &str, files: &mut Vec<String>) -> io::Result<()> {
+    let entries = match fs::read_dir(outdir.join(relative_dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let relative_path = format!("{}/{}", relative_dir, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_files(outdir, &relative_path, files)?;
+        } else {
+            files.push(relative_path);
+        }
+    }
+    Ok(())
+}
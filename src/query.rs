@@ -0,0 +1,51 @@
+use crate::{note_index, Args};
+use anyhow::anyhow;
+use colored::*;
+use std::path::Path;
+
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Part of the attribute path to look for, ignoring case
+    pattern: String,
+}
+
+/// Lists the notes in `--outdir` whose attribute path contains the pattern,
+/// from the note index the last run wrote.
+pub fn run(query_args: &QueryArgs, args: &Args) -> anyhow::Result<()> {
+    if !Path::new(&note_index::path(&args.outdir)).is_file() {
+        return Err(anyhow!(
+            "{} has no note-index.jsonl, generate a vault first",
+            args.outdir
+        ));
+    }
+
+    let pattern = query_args.pattern.to_lowercase();
+    let mut matches: Vec<(String, String)> = note_index::load(&args.outdir)
+        .notes
+        .into_iter()
+        .filter(|(package, _)| package.to_lowercase().contains(&pattern))
+        .collect();
+    matches.sort();
+
+    if matches.is_empty() {
+        println!(
+            "{} {}",
+            "🔎 No packages matching:".yellow().bold(),
+            query_args.pattern.bright_white()
+        );
+        return Ok(());
+    }
+    println!(
+        "{} {}",
+        "🔎 Packages matching:".cyan().bold(),
+        matches.len().to_string().bright_white()
+    );
+    for (package, note) in matches {
+        println!(
+            "   {} {}",
+            package.green(),
+            format!("{}/{}", args.outdir, note).bright_black()
+        );
+    }
+    Ok(())
+}
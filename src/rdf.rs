@@ -0,0 +1,413 @@
+use crate::drv_filename;
+use crate::export::CachedPackage;
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RdfSyntax {
+    /// Turtle, with prefixes
+    Turtle,
+    /// JSON-LD, with the prefixes in its context
+    Jsonld,
+}
+
+/// Namespace of the terms DOAP and FOAF have no word for, and of the
+/// packages, maintainers and derivations themselves.
+const BASE: &str = "https://osbm.dev/nixpkgs-vault/";
+
+/// Where SPDX publishes its license list, one IRI per identifier.
+const SPDX: &str = "http://spdx.org/licenses/";
+
+const PREFIXES: &[(&str, &str)] = &[
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("doap", "http://usefulinc.com/ns/doap#"),
+    ("foaf", "http://xmlns.com/foaf/0.1/"),
+    ("spdx", SPDX),
+    ("nv", "https://osbm.dev/nixpkgs-vault/ns#"),
+];
+
+/// The ontology: terms of the `nv:` namespace, as (term, kind, domain,
+/// range, comment).
+const ONTOLOGY: &[(&str, &str, &str, &str, &str)] = &[
+    (
+        "nv:Derivation",
+        "rdfs:Class",
+        "",
+        "",
+        "A Nix store derivation",
+    ),
+    (
+        "nv:attribute",
+        "rdf:Property",
+        "doap:Project",
+        "",
+        "Attribute path of the package in nixpkgs",
+    ),
+    (
+        "nv:licenseName",
+        "rdf:Property",
+        "doap:Project",
+        "",
+        "Short name of the license in nixpkgs",
+    ),
+    (
+        "nv:available",
+        "rdf:Property",
+        "doap:Project",
+        "",
+        "Whether the package evaluates on the platform of the vault",
+    ),
+    (
+        "nv:broken",
+        "rdf:Property",
+        "doap:Project",
+        "",
+        "Whether the package is marked broken",
+    ),
+    (
+        "nv:platform",
+        "rdf:Property",
+        "doap:Project",
+        "",
+        "System the package supports, e.g. x86_64-linux",
+    ),
+    (
+        "nv:derivation",
+        "rdf:Property",
+        "doap:Project",
+        "nv:Derivation",
+        "Derivation building the package",
+    ),
+    (
+        "nv:dependsOn",
+        "rdf:Property",
+        "doap:Project",
+        "nv:Derivation",
+        "Derivation the package's derivation takes as input",
+    ),
+    (
+        "nv:storePath",
+        "rdf:Property",
+        "nv:Derivation",
+        "",
+        "Store path of the derivation",
+    ),
+];
+
+enum Term {
+    /// A full IRI
+    Iri(String),
+    /// A prefixed name, e.g. `doap:Project`
+    Name(&'static str),
+    Literal(String),
+    Boolean(bool),
+    /// A blank node with these properties
+    Blank(Vec<(&'static str, Term)>),
+}
+
+/// A subject with its properties, values of one predicate kept together.
+struct Resource {
+    iri: Term,
+    properties: Vec<(&'static str, Vec<Term>)>,
+}
+
+impl Resource {
+    fn new(iri: Term, class: &'static str) -> Self {
+        Resource {
+            iri,
+            properties: vec![("a", vec![Term::Name(class)])],
+        }
+    }
+
+    fn add(&mut self, predicate: &'static str, object: Term) {
+        match self.properties.iter_mut().find(|(p, _)| *p == predicate) {
+            Some((_, objects)) => objects.push(object),
+            None => self.properties.push((predicate, vec![object])),
+        }
+    }
+}
+
+/// Percent-encodes what may not appear in a path segment of an IRI.
+fn segment(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~+@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Percent-encodes what Turtle doesn't allow in an IRI, as metadata URLs
+/// occasionally have spaces or braces.
+fn iri(value: &str) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        if c <= ' ' || "<>\"{}|^`\\".contains(c) {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn derivation_iri(drv_path: &str) -> Term {
+    Term::Iri(format!("{}drv/{}", BASE, segment(drv_filename(drv_path))))
+}
+
+/// Only links to the web are kept, not a maintainer's home directory.
+fn web_iri(url: &str) -> Option<Term> {
+    (url.starts_with("https://") || url.starts_with("http://")).then(|| Term::Iri(iri(url)))
+}
+
+/// The resources describing `packages`, their maintainers and derivations.
+fn resources(packages: &[CachedPackage]) -> Vec<Resource> {
+    let mut projects = Vec::new();
+    let mut maintainers = BTreeMap::new();
+    let mut derivations = BTreeSet::new();
+    for CachedPackage {
+        package_info,
+        has_derivation,
+        spdx_ids,
+    } in packages
+    {
+        let mut project = Resource::new(
+            Term::Iri(format!(
+                "{}package/{}",
+                BASE,
+                segment(&package_info.attribute)
+            )),
+            "doap:Project",
+        );
+        project.add("doap:name", Term::Literal(package_info.name.clone()));
+        project.add(
+            "nv:attribute",
+            Term::Literal(package_info.attribute.clone()),
+        );
+        if let Some(ref description) = package_info.description {
+            project.add("doap:shortdesc", Term::Literal(description.clone()));
+        }
+        if let Some(ref long_description) = package_info.long_description {
+            project.add("doap:description", Term::Literal(long_description.clone()));
+        }
+        if let Some(homepage) = package_info.homepage.as_deref().and_then(web_iri) {
+            project.add("doap:homepage", homepage);
+        }
+        if let Some(download_page) = package_info.download_page.as_deref().and_then(web_iri) {
+            project.add("doap:download-page", download_page);
+        }
+        project.add(
+            "doap:release",
+            Term::Blank(vec![
+                ("a", Term::Name("doap:Version")),
+                ("doap:revision", Term::Literal(package_info.version.clone())),
+            ]),
+        );
+        for spdx_id in spdx_ids {
+            project.add(
+                "doap:license",
+                Term::Iri(format!("{}{}", SPDX, segment(spdx_id))),
+            );
+        }
+        project.add(
+            "nv:licenseName",
+            Term::Literal(package_info.license_short_name.clone()),
+        );
+        for maintainer in &package_info.maintainers {
+            let handle = maintainer.handle();
+            let person = Term::Iri(format!("{}maintainer/{}", BASE, segment(&handle)));
+            maintainers.entry(handle).or_insert(maintainer);
+            project.add("doap:maintainer", person);
+        }
+        project.add("nv:available", Term::Boolean(package_info.available));
+        project.add("nv:broken", Term::Boolean(package_info.broken));
+        for platform in &package_info.platforms {
+            project.add("nv:platform", Term::Literal(platform.clone()));
+        }
+        if *has_derivation {
+            project.add("nv:derivation", derivation_iri(&package_info.drv_path));
+            derivations.insert(package_info.drv_path.as_str());
+            for dependency in &package_info.dependencies {
+                project.add("nv:dependsOn", derivation_iri(dependency));
+                derivations.insert(dependency.as_str());
+            }
+        }
+        projects.push(project);
+    }
+
+    let people = maintainers.into_iter().map(|(handle, maintainer)| {
+        let mut person = Resource::new(
+            Term::Iri(format!("{}maintainer/{}", BASE, segment(&handle))),
+            "foaf:Person",
+        );
+        if let Some(ref name) = maintainer.name {
+            person.add("foaf:name", Term::Literal(name.clone()));
+        }
+        if let Some(ref email) = maintainer.email {
+            person.add("foaf:mbox", Term::Iri(iri(&format!("mailto:{}", email))));
+        }
+        if let Some(ref github) = maintainer.github {
+            person.add("foaf:nick", Term::Literal(github.clone()));
+            person.add(
+                "foaf:account",
+                Term::Iri(format!("https://github.com/{}", segment(github))),
+            );
+        }
+        person
+    });
+    let derivations = derivations.into_iter().map(|drv_path| {
+        let mut derivation = Resource::new(derivation_iri(drv_path), "nv:Derivation");
+        derivation.add("nv:storePath", Term::Literal(drv_path.to_string()));
+        derivation
+    });
+    let ontology = ONTOLOGY.iter().map(|(term, kind, domain, range, comment)| {
+        let mut resource = Resource::new(Term::Name(term), kind);
+        resource.add("rdfs:comment", Term::Literal(comment.to_string()));
+        if !domain.is_empty() {
+            resource.add("rdfs:domain", Term::Name(domain));
+        }
+        if !range.is_empty() {
+            resource.add("rdfs:range", Term::Name(range));
+        }
+        resource
+    });
+    ontology
+        .chain(projects)
+        .chain(people)
+        .chain(derivations)
+        .collect()
+}
+
+/// A Turtle string, escaped as a short quoted literal.
+fn turtle_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn turtle_term(term: &Term) -> String {
+    match term {
+        Term::Iri(iri) => format!("<{}>", iri),
+        Term::Name(name) => name.to_string(),
+        Term::Literal(value) => turtle_string(value),
+        Term::Boolean(value) => value.to_string(),
+        Term::Blank(properties) => {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|(predicate, object)| format!("{} {}", predicate, turtle_term(object)))
+                .collect();
+            format!("[ {} ]", properties.join(" ; "))
+        }
+    }
+}
+
+fn write_turtle(writer: &mut dyn Write, resources: &[Resource]) -> std::io::Result<()> {
+    for (prefix, namespace) in PREFIXES {
+        writeln!(writer, "@prefix {}: <{}> .", prefix, namespace)?;
+    }
+    for resource in resources {
+        writeln!(writer)?;
+        writeln!(writer, "{}", turtle_term(&resource.iri))?;
+        let last = resource.properties.len() - 1;
+        for (index, (predicate, objects)) in resource.properties.iter().enumerate() {
+            let objects: Vec<String> = objects.iter().map(turtle_term).collect();
+            let end = if index == last { "." } else { ";" };
+            writeln!(writer, "    {} {} {}", predicate, objects.join(", "), end)?;
+        }
+    }
+    Ok(())
+}
+
+fn jsonld_term(term: &Term) -> Value {
+    match term {
+        Term::Iri(iri) => json!({ "@id": iri }),
+        Term::Name(name) => json!({ "@id": name }),
+        Term::Literal(value) => json!(value),
+        Term::Boolean(value) => json!(value),
+        Term::Blank(properties) => {
+            let mut object = Map::new();
+            for (predicate, value) in properties {
+                match (predicate, value) {
+                    (&"a", Term::Name(class)) => object.insert("@type".into(), json!(class)),
+                    _ => object.insert(predicate.to_string(), jsonld_term(value)),
+                };
+            }
+            Value::Object(object)
+        }
+    }
+}
+
+fn jsonld_node(resource: &Resource) -> Value {
+    let mut node = Map::new();
+    let id = match &resource.iri {
+        Term::Iri(iri) => iri.clone(),
+        Term::Name(name) => name.to_string(),
+        _ => unreachable!("subjects are named"),
+    };
+    node.insert("@id".into(), json!(id));
+    for (predicate, objects) in &resource.properties {
+        let (key, values): (String, Vec<Value>) = if *predicate == "a" {
+            let classes = objects.iter().map(|class| match class {
+                Term::Name(name) => json!(name),
+                _ => unreachable!("classes are prefixed names"),
+            });
+            ("@type".into(), classes.collect())
+        } else {
+            (
+                predicate.to_string(),
+                objects.iter().map(jsonld_term).collect(),
+            )
+        };
+        let value = match <[Value; 1]>::try_from(values) {
+            Ok([value]) => value,
+            Err(values) => Value::Array(values),
+        };
+        node.insert(key, value);
+    }
+    Value::Object(node)
+}
+
+fn write_jsonld(writer: &mut dyn Write, resources: &[Resource]) -> anyhow::Result<()> {
+    let context: Map<String, Value> = PREFIXES
+        .iter()
+        .map(|(prefix, namespace)| (prefix.to_string(), json!(namespace)))
+        .collect();
+    let document = json!({
+        "@context": context,
+        "@graph": resources.iter().map(jsonld_node).collect::<Vec<_>>(),
+    });
+    serde_json::to_writer_pretty(&mut *writer, &document)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes `packages`, their maintainers, licenses and dependencies, and the
+/// `nv:` terms describing them.
+pub fn write(
+    writer: &mut dyn Write,
+    syntax: RdfSyntax,
+    packages: &[CachedPackage],
+) -> anyhow::Result<()> {
+    let resources = resources(packages);
+    match syntax {
+        RdfSyntax::Turtle => write_turtle(writer, &resources)?,
+        RdfSyntax::Jsonld => write_jsonld(writer, &resources)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::site::{self, FrontmatterStyle};
+use crate::{
+    front_matter_list, generate_package_note_template, prepare_package_info,
+    strip_generated_footer, write_if_changed, yaml_list, Args, OutputFormat,
+};
+use anyhow::bail;
+use colored::*;
+use rayon::prelude::*;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sections rendered from the derivation, which a fresh note only has an
+/// empty Build Information section for.
+const DERIVATION_SECTIONS: [&str; 4] = [
+    "## 🔧 Build Information",
+    "## 📦 Source",
+    "## 🔗 Dependencies",
+    "## 📁 Input Sources",
+];
+
+/// Sections rendered from packages.json, the only ones that get replaced.
+/// Everything else, like the derivation sections or those of
+/// `--reverse-deps`, is kept as it is.
+const METADATA_SECTIONS: [&str; 4] = [
+    "## 📝 Description",
+    "## 📋 Package Information",
+    "## 👥 Maintainers",
+    "## 🏢 Teams",
+];
+
+fn is_derivation_section(section: &str) -> bool {
+    DERIVATION_SECTIONS
+        .iter()
+        .any(|heading| section.starts_with(heading))
+}
+
+fn is_metadata_section(section: &str) -> bool {
+    METADATA_SECTIONS
+        .iter()
+        .any(|heading| section.starts_with(heading))
+}
+
+/// Tags of a fresh note merged with the derivation tags of the existing
+/// one, in the order `generate_package_note_template` writes them: status
+/// tags, then derivation checks, then the rest, with outputs last.
+fn merge_tags<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<&'a str> {
+    let is_status = |tag: &&str| matches!(*tag, "broken" | "not-available");
+    let is_check = |tag: &&str| matches!(*tag, "missing-drv" | "hash-mismatch");
+    let is_output = |tag: &&str| tag.starts_with("outputs/");
+
+    let mut tags: Vec<&str> = new.iter().copied().filter(is_status).collect();
+    tags.extend(old.iter().copied().filter(is_check));
+    tags.extend(new.iter().copied().filter(|tag| !is_status(tag)));
+    tags.extend(old.iter().copied().filter(is_output));
+    tags
+}
+
+/// Splits a note into its front matter (without the `---` fences) and body.
+fn split_front_matter(content: &str) -> Option<(&str, &str)> {
+    content.strip_prefix("---\n")?.split_once("\n---\n")
+}
+
+/// The body cut before every `## ` heading; the first piece is the title.
+fn sections(body: &str) -> Vec<&str> {
+    let mut starts: Vec<usize> = body.match_indices("\n## ").map(|(i, _)| i + 1).collect();
+    starts.insert(0, 0);
+    starts.push(body.len());
+    starts.windows(2).map(|w| &body[w[0]..w[1]]).collect()
+}
+
+/// Rebuilds a note from fresh metadata, carrying over the derivation tags
+/// and every section not rendered from metadata of the existing note.
+fn merge_note(existing: &str, fresh: &str) -> Option<String> {
+    let (old_front, old_body) = split_front_matter(existing)?;
+    let (new_front, new_body) = split_front_matter(fresh)?;
+    let footer = &fresh[strip_generated_footer(fresh).len()..];
+
+    // The first piece is the title, which is rendered from metadata too
+    let old_sections = sections(strip_generated_footer(old_body));
+    let mut kept = old_sections
+        .iter()
+        .skip(1)
+        .filter(|section| !is_metadata_section(section));
+
+    // Tags are the last key of the front matter
+    let (before_tags, _) = new_front.split_once("tags:\n").unwrap_or((new_front, ""));
+    let old_tags = front_matter_list(old_front, "tags");
+    let new_tags = front_matter_list(new_front, "tags");
+    let tags = merge_tags(
+        &old_tags.iter().map(String::as_str).collect::<Vec<_>>(),
+        &new_tags.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    let mut content = format!("---\n{}", before_tags);
+    content.push_str(&yaml_list("tags", tags.into_iter().map(str::to_string)));
+    content.push_str("---\n");
+
+    // Fresh notes render an empty Build Information section in place of all
+    // derivation sections, which is where the kept ones go
+    for section in sections(strip_generated_footer(new_body)) {
+        if is_derivation_section(section) {
+            kept.by_ref().for_each(|section| content.push_str(section));
+        } else {
+            content.push_str(section);
+        }
+    }
+    kept.for_each(|section| content.push_str(section));
+
+    content.push_str(footer);
+    Some(content)
+}
+
+fn collect_notes(dir: &Path, extension: &str, notes: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_notes(&path, extension, notes);
+        } else if path.extension().is_some_and(|ext| ext == extension) {
+            notes.push(path);
+        }
+    }
+}
+
+/// Rewrites the metadata of every existing note from `packages`, without
+/// evaluating anything. Notes whose package is gone are left alone.
+pub fn run(
+    packages: &Map<String, Value>,
+    rename_map: &[(String, String)],
+    args: &Args,
+) -> anyhow::Result<()> {
+    if args.format != OutputFormat::Markdown {
+        bail!("--refresh-metadata only supports markdown notes");
+    }
+    // Their metadata lives in a front matter of another shape, with links
+    // already rewritten for the site
+    if args.frontmatter_style != FrontmatterStyle::Obsidian {
+        bail!("--refresh-metadata only supports Obsidian notes, generate Hugo and Jekyll notes again instead");
+    }
+
+    let mut notes = Vec::new();
+    collect_notes(
+        &Path::new(&args.outdir).join("packages"),
+        args.notes_extension(),
+        &mut notes,
+    );
+    println!(
+        "{} {}",
+        "🔄 Refreshing metadata of notes:".cyan().bold(),
+        notes.len().to_string().bright_white()
+    );
+
+    let refreshed = AtomicUsize::new(0);
+    let orphaned = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let site_notes = AtomicUsize::new(0);
+    notes.par_iter().for_each(|path| {
+        let Ok(existing) = fs::read_to_string(path) else {
+            failed.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        if site::is_site_note(&existing) {
+            site_notes.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // The attribute is the last alias; it's only listed separately
+        // when --rename-map changed the display name
+        let attribute = split_front_matter(&existing)
+            .and_then(|(front, _)| front_matter_list(front, "aliases").pop());
+        let Some((name, info)) = attribute.and_then(|a| packages.get_key_value(&a)) else {
+            orphaned.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        let package_info = prepare_package_info(name, info, args, rename_map);
+        let fresh =
+            generate_package_note_template(&package_info, args.notes_extension(), args.frontmatter);
+        let written = merge_note(&existing, &fresh)
+            .map(|content| write_if_changed(&path.to_string_lossy(), &content));
+        match written {
+            Some(Ok(true)) => {
+                refreshed.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(Ok(false)) => {}
+            Some(Err(_)) | None => {
+                failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    println!(
+        "{} {} {} {} {} {}",
+        "✅ Notes refreshed:".green().bold(),
+        refreshed.into_inner().to_string().bright_white(),
+        "without package:".yellow().bold(),
+        orphaned.into_inner().to_string().bright_white(),
+        "failed:".yellow().bold(),
+        failed.into_inner().to_string().bright_white()
+    );
+    let site_notes = site_notes.into_inner();
+    if site_notes > 0 {
+        bail!(
+            "{} notes have Hugo or Jekyll front matter, which --refresh-metadata can't rewrite; generate them again instead",
+            site_notes
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOOTER: &str = "---\n*Generated on 2026-01-01 00:00:00 UTC*\n";
+
+    #[test]
+    fn sections_not_rendered_from_metadata_are_kept() {
+        // Written before list items were quoted
+        let existing = format!(
+            "---\naliases:\n  - hello\ntags:\n  - missing-drv\n  - license/mit\n  - outputs/out\n---\n\n\
+             # hello\n\n\
+             ## 📝 Description\n\nOld description\n\n\
+             ## 📋 Package Information\n\n- **Version**: `2.12`\n\n\
+             ## 👥 Maintainers\n\n- Gone\n\n\
+             ## 🔧 Build Information\n\n- **Derivation Path**: `/nix/store/x-hello.drv`\n\n\
+             ## 🔗 Dependencies\n\n- [[y-glibc.drv|glibc]]\n\n\
+             ## ⬅️ Reverse Dependencies\n\n- [[z-cowsay.drv|cowsay]]\n\n\
+             {}",
+            FOOTER
+        );
+        let fresh = format!(
+            "---\naliases:\n  - \"hello\"\ntags:\n  - \"license/gpl3Plus\"\n---\n\n\
+             # hello\n\n\
+             ## 📝 Description\n\nNew description\n\n\
+             ## 📋 Package Information\n\n- **Version**: `2.12.1`\n\n\
+             ## 🔧 Build Information\n\n- **Derivation Path**: ``\n\n\
+             {}",
+            FOOTER
+        );
+
+        assert_eq!(
+            merge_note(&existing, &fresh).unwrap(),
+            format!(
+                "---\naliases:\n  - \"hello\"\ntags:\n  - \"missing-drv\"\n  \
+                 - \"license/gpl3Plus\"\n  - \"outputs/out\"\n---\n\n\
+                 # hello\n\n\
+                 ## 📝 Description\n\nNew description\n\n\
+                 ## 📋 Package Information\n\n- **Version**: `2.12.1`\n\n\
+                 ## 🔧 Build Information\n\n- **Derivation Path**: `/nix/store/x-hello.drv`\n\n\
+                 ## 🔗 Dependencies\n\n- [[y-glibc.drv|glibc]]\n\n\
+                 ## ⬅️ Reverse Dependencies\n\n- [[z-cowsay.drv|cowsay]]\n\n\
+                 {}",
+                FOOTER
+            )
+        );
+    }
+}
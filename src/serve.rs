@@ -0,0 +1,187 @@
+use crate::{html, Args};
+use anyhow::anyhow;
+use colored::*;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tiny_http::{Header, Request, Response, Server};
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: String,
+
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+}
+
+/// Serves the vault in `--outdir` over HTTP, rendering markdown notes to
+/// HTML as they are requested. The index is built once at startup.
+pub fn run(serve_args: &ServeArgs, args: &Args) -> anyhow::Result<()> {
+    let outdir = Path::new(&args.outdir);
+    if !outdir.is_dir() {
+        return Err(anyhow!(
+            "{} is not a directory, generate a vault first",
+            args.outdir
+        ));
+    }
+
+    let mut entries = Vec::new();
+    collect_entries(outdir, Path::new("packages"), &mut entries);
+    let index = html::render_index(entries);
+
+    let address = format!("{}:{}", serve_args.bind, serve_args.port);
+    let server =
+        Server::http(&address).map_err(|e| anyhow!("failed to listen on {}: {}", address, e))?;
+    println!(
+        "{} {}",
+        "🌐 Serving vault at:".cyan().bold(),
+        format!("http://{}/", address).bright_white()
+    );
+
+    for request in server.incoming_requests() {
+        let response = respond(&request, outdir, &index);
+        if let Err(e) = request.respond(response) {
+            eprintln!(
+                "{} {}",
+                "⚠️  Failed to send response:".yellow().bold(),
+                e.to_string().red()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn respond(request: &Request, outdir: &Path, index: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let url = request.url();
+    let path = percent_decode(url.split(['?', '#']).next().unwrap_or(url));
+    let relative_path = path.trim_start_matches('/');
+
+    if relative_path.is_empty() || relative_path == "index.html" {
+        return page(index.to_string(), "text/html; charset=utf-8");
+    }
+
+    // Never leave the output directory
+    let Some(file) = resolve(outdir, relative_path) else {
+        return not_found();
+    };
+
+    // Notes are rendered on the fly, anything else is served as is
+    if let Some(stem) = relative_path.strip_suffix(".html") {
+        let note = outdir.join(format!("{}.md", stem));
+        if let Ok(markdown) = fs::read_to_string(&note) {
+            let title = note_title(&markdown).unwrap_or(stem.rsplit('/').next().unwrap_or(stem));
+            return page(
+                html::render_page(title, &markdown, relative_path),
+                "text/html; charset=utf-8",
+            );
+        }
+    }
+
+    match fs::read(&file) {
+        Ok(data) if file.is_file() => Response::from_data(data).with_header(content_type(
+            file.extension().and_then(|e| e.to_str()).unwrap_or(""),
+        )),
+        _ => not_found(),
+    }
+}
+
+/// `relative_path` joined to `outdir`, or `None` if it could point outside.
+fn resolve(outdir: &Path, relative_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative_path);
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| outdir.join(relative))
+}
+
+/// Index entries for every note below `relative_dir`, linking to the page
+/// the server renders for it.
+fn collect_entries(outdir: &Path, relative_dir: &Path, entries: &mut Vec<html::IndexEntry>) {
+    let Ok(dir) = fs::read_dir(outdir.join(relative_dir)) else {
+        return;
+    };
+    for entry in dir.flatten() {
+        let relative_path = relative_dir.join(entry.file_name());
+        if entry.path().is_dir() {
+            collect_entries(outdir, &relative_path, entries);
+            continue;
+        }
+        if relative_path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Ok(markdown) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let path = relative_path.with_extension("html");
+        entries.push(html::IndexEntry {
+            name: note_title(&markdown)
+                .map(|title| title.to_string())
+                .unwrap_or_else(|| path.to_string_lossy().to_string()),
+            path: path.to_string_lossy().to_string(),
+            description: markdown
+                .lines()
+                .find_map(|line| line.strip_prefix("- **Description**: "))
+                .map(|description| description.to_string()),
+        });
+    }
+}
+
+/// The note's first `# ` heading.
+fn note_title(markdown: &str) -> Option<&str> {
+    markdown
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim())
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones untouched.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn content_type(extension: &str) -> Header {
+    let value = match extension {
+        "html" => "text/html; charset=utf-8",
+        "md" | "typ" | "dot" | "mmd" | "txt" => "text/plain; charset=utf-8",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "pdf" => "application/pdf",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        _ => "application/octet-stream",
+    };
+    Header::from_bytes("Content-Type", value).expect("static header is valid")
+}
+
+fn page(body: String, content_type: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(body.into_bytes()).with_header(
+        Header::from_bytes("Content-Type", content_type).expect("static header is valid"),
+    )
+}
+
+fn not_found() -> Response<std::io::Cursor<Vec<u8>>> {
+    page("404 Not Found".to_string(), "text/plain; charset=utf-8").with_status_code(404)
+}
@@ -0,0 +1,216 @@
+use crate::{html, note_tags, strip_generated_footer, yaml_list, yaml_string, PackageInfo};
+use chrono::Utc;
+use clap::ValueEnum;
+use std::fs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FrontmatterStyle {
+    /// YAML aliases and tags for Obsidian, with wikilinks between notes
+    Obsidian,
+    /// TOML front matter for Hugo, with relative markdown links
+    Hugo,
+    /// YAML front matter for Jekyll, with relative markdown links
+    Jekyll,
+}
+
+/// Front matter a static site generator reads: `title`, `date`,
+/// `description` and `tags` as it expects them, the package metadata as
+/// `params` for Hugo and as top-level variables for Jekyll.
+fn front_matter(style: FrontmatterStyle, package_info: &PackageInfo) -> String {
+    let date = Utc::now();
+    let maintainers = package_info
+        .maintainers
+        .iter()
+        .map(|maintainer| maintainer.handle());
+    match style {
+        FrontmatterStyle::Obsidian => unreachable!("Obsidian notes keep their own front matter"),
+        FrontmatterStyle::Hugo => {
+            let strings = |values: &[String]| {
+                toml::Value::Array(values.iter().cloned().map(toml::Value::String).collect())
+            };
+            let mut params = toml::Table::new();
+            params.insert("attribute".into(), package_info.attribute.clone().into());
+            params.insert("version".into(), package_info.version.clone().into());
+            params.insert(
+                "license".into(),
+                package_info.license_short_name.clone().into(),
+            );
+            params.insert("available".into(), package_info.available.into());
+            params.insert("broken".into(), package_info.broken.into());
+            if let Some(ref homepage) = package_info.homepage {
+                params.insert("homepage".into(), homepage.clone().into());
+            }
+            params.insert("platforms".into(), strings(&package_info.platforms));
+            params.insert(
+                "maintainers".into(),
+                strings(&maintainers.collect::<Vec<_>>()),
+            );
+            params.insert("drv_path".into(), package_info.drv_path.clone().into());
+
+            let mut table = toml::Table::new();
+            table.insert("title".into(), package_info.name.clone().into());
+            let date = date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            if let Ok(date) = date.parse::<toml::value::Datetime>() {
+                table.insert("date".into(), toml::Value::Datetime(date));
+            }
+            if let Some(ref description) = package_info.description {
+                table.insert("description".into(), description.clone().into());
+            }
+            table.insert("tags".into(), strings(&note_tags(package_info)));
+            table.insert("params".into(), toml::Value::Table(params));
+            format!("+++\n{}+++\n", toml::to_string(&table).unwrap_or_default())
+        }
+        FrontmatterStyle::Jekyll => {
+            let mut content = String::from("---\n");
+            content.push_str(&format!("title: {}\n", yaml_string(&package_info.name)));
+            content.push_str(&format!("date: {}\n", date.format("%Y-%m-%d %H:%M:%S %z")));
+            if let Some(ref description) = package_info.description {
+                content.push_str(&format!("description: {}\n", yaml_string(description)));
+            }
+            content.push_str(&yaml_list("tags", note_tags(package_info)));
+            content.push_str(&format!(
+                "attribute: {}\n",
+                yaml_string(&package_info.attribute)
+            ));
+            content.push_str(&format!(
+                "version: {}\n",
+                yaml_string(&package_info.version)
+            ));
+            content.push_str(&format!(
+                "license: {}\n",
+                yaml_string(&package_info.license_short_name)
+            ));
+            content.push_str(&format!("available: {}\n", package_info.available));
+            content.push_str(&format!("broken: {}\n", package_info.broken));
+            if let Some(ref homepage) = package_info.homepage {
+                content.push_str(&format!("homepage: {}\n", yaml_string(homepage)));
+            }
+            content.push_str(&yaml_list(
+                "platforms",
+                package_info.platforms.iter().cloned(),
+            ));
+            content.push_str(&yaml_list("maintainers", maintainers));
+            content.push_str(&format!(
+                "drv_path: {}\n",
+                yaml_string(&package_info.drv_path)
+            ));
+            content.push_str("---\n");
+            content
+        }
+    }
+}
+
+/// The note with its Obsidian front matter replaced by `style`'s, and the
+/// title left to the front matter. Wikilinks are kept, so they can still be
+/// resolved for `--layout attr-tree`.
+pub fn with_front_matter(
+    style: FrontmatterStyle,
+    package_info: &PackageInfo,
+    markdown: &str,
+) -> String {
+    let body = html::strip_front_matter(markdown).trim_start();
+    let title = format!("# {}\n", package_info.name);
+    let body = body.strip_prefix(&title).unwrap_or(body).trim_start();
+    format!("{}\n{}", front_matter(style, package_info), body)
+}
+
+/// Rewrites the wikilinks of the note at `relative_path` to relative
+/// markdown links to the notes they point at.
+pub fn convert_links(content: &str, relative_path: &str, extension: &str) -> String {
+    html::convert_wikilinks(content, &html::root_prefix(relative_path), extension)
+}
+
+/// The `date` line of a Hugo or Jekyll front matter.
+fn date_line(content: &str) -> Option<&str> {
+    let (fence, front_matter) = [("+++", "+++\n"), ("---", "---\n")]
+        .into_iter()
+        .find_map(|(fence, opening)| Some((fence, content.strip_prefix(opening)?)))?;
+    front_matter
+        .lines()
+        .take_while(|line| *line != fence)
+        .find(|line| line.starts_with("date = ") || line.starts_with("date: "))
+}
+
+/// `content` with the date of the note already at `path`, if nothing but
+/// the date and the footer differ. Notes then keep the date they last
+/// changed on, and unchanged ones aren't written again.
+pub fn keep_unchanged_date(content: String, path: &str) -> String {
+    let Ok(existing) = fs::read_to_string(path) else {
+        return content;
+    };
+    let (Some(date), Some(previous)) = (date_line(&content), date_line(&existing)) else {
+        return content;
+    };
+    let dated = content.replacen(date, previous, 1);
+    if strip_generated_footer(&dated) == strip_generated_footer(&existing) {
+        dated
+    } else {
+        content
+    }
+}
+
+/// Whether a note has the front matter of `--frontmatter-style hugo` or
+/// `jekyll` rather than Obsidian's, which has no `title`.
+pub fn is_site_note(content: &str) -> bool {
+    content.starts_with("+++\n")
+        || content
+            .strip_prefix("---\n")
+            .and_then(|rest| rest.split_once("\n---\n"))
+            .is_some_and(|(front_matter, _)| {
+                front_matter.lines().any(|line| line.starts_with("title: "))
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_info_from_meta;
+    use serde_json::json;
+
+    fn note(style: FrontmatterStyle) -> String {
+        let info = json!({ "version": "2.12.1", "meta": { "description": "Greeting" } });
+        let package_info = package_info_from_meta("hello", &info);
+        let markdown =
+            "---\naliases:\n  - hello\n---\n\n# hello\n\nBody\n---\n*Generated on now*\n";
+        with_front_matter(style, &package_info, markdown)
+    }
+
+    #[test]
+    fn unchanged_notes_keep_their_date() {
+        let dir = std::env::temp_dir().join(format!("nixpkgs-vault-site-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello.md").to_string_lossy().into_owned();
+
+        for (style, old_date) in [
+            (FrontmatterStyle::Hugo, "date = 2020-01-01T00:00:00Z"),
+            (FrontmatterStyle::Jekyll, "date: 2020-01-01 00:00:00 +0000"),
+        ] {
+            let fresh = note(style);
+            let date = date_line(&fresh).unwrap();
+            assert_ne!(date, old_date);
+
+            let existing = fresh
+                .replacen(date, old_date, 1)
+                .replace("Generated on now", "Generated on then");
+            fs::write(&path, &existing).unwrap();
+            let kept = keep_unchanged_date(fresh.clone(), &path);
+            assert_eq!(date_line(&kept), Some(old_date));
+            assert!(!crate::write_if_changed(&path, &kept).unwrap());
+
+            // A changed body gets the date of this run
+            fs::write(&path, existing.replace("Body", "Old body")).unwrap();
+            assert_eq!(keep_unchanged_date(fresh.clone(), &path), fresh);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn site_notes_are_told_apart_from_obsidian_notes() {
+        assert!(is_site_note(&note(FrontmatterStyle::Hugo)));
+        assert!(is_site_note(&note(FrontmatterStyle::Jekyll)));
+        assert!(!is_site_note(
+            "---\nname: \"hello\"\naliases:\n  - hello\n---\n\n# hello\n"
+        ));
+        assert!(!is_site_note("# hello\n\ntitle: not front matter\n"));
+    }
+}
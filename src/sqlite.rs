@@ -0,0 +1,259 @@
+use crate::PackageInfo;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::sync::Mutex;
+
+/// Stored as `PRAGMA user_version`. Bumped whenever a table or column is
+/// changed or removed, so downstream tools can refuse a schema they don't
+/// know; adding tables doesn't bump it.
+pub const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE licenses (
+    id INTEGER PRIMARY KEY,
+    short_name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE maintainers (
+    id INTEGER PRIMARY KEY,
+    handle TEXT NOT NULL UNIQUE,
+    name TEXT,
+    email TEXT,
+    github TEXT
+);
+CREATE TABLE packages (
+    id INTEGER PRIMARY KEY,
+    attribute TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    description TEXT,
+    homepage TEXT,
+    available INTEGER NOT NULL,
+    broken INTEGER NOT NULL,
+    broken_reason TEXT,
+    priority INTEGER,
+    position TEXT,
+    drv_path TEXT NOT NULL,
+    license_id INTEGER NOT NULL REFERENCES licenses (id)
+);
+CREATE INDEX packages_drv_path ON packages (drv_path);
+CREATE TABLE package_maintainers (
+    package_id INTEGER NOT NULL REFERENCES packages (id),
+    maintainer_id INTEGER NOT NULL REFERENCES maintainers (id),
+    PRIMARY KEY (package_id, maintainer_id)
+);
+CREATE TABLE outputs (
+    package_id INTEGER NOT NULL REFERENCES packages (id),
+    name TEXT NOT NULL,
+    path TEXT,
+    installed_by_default INTEGER NOT NULL,
+    PRIMARY KEY (package_id, name)
+);
+CREATE TABLE platforms (
+    package_id INTEGER NOT NULL REFERENCES packages (id),
+    platform TEXT NOT NULL,
+    PRIMARY KEY (package_id, platform)
+);
+CREATE TABLE dependencies (
+    package_id INTEGER NOT NULL REFERENCES packages (id),
+    drv_path TEXT NOT NULL,
+    PRIMARY KEY (package_id, drv_path)
+);
+CREATE INDEX dependencies_drv_path ON dependencies (drv_path);
+";
+
+/// Packages written per transaction, as committing is what costs.
+const BATCH_SIZE: usize = 512;
+
+struct MaintainerRow {
+    handle: String,
+    name: Option<String>,
+    email: Option<String>,
+    github: Option<String>,
+}
+
+struct Record {
+    attribute: String,
+    name: String,
+    version: String,
+    description: Option<String>,
+    homepage: Option<String>,
+    available: bool,
+    broken: bool,
+    broken_reason: Option<String>,
+    priority: Option<i64>,
+    position: Option<String>,
+    drv_path: String,
+    license: String,
+    maintainers: Vec<MaintainerRow>,
+    outputs: Vec<(String, Option<String>, bool)>,
+    platforms: Vec<String>,
+    dependencies: Vec<String>,
+}
+
+/// Normalized SQLite database written with `--sqlite`. Workers queue
+/// records and whichever fills the batch commits it, as with `--db`.
+pub struct SqliteExport {
+    connection: Mutex<Connection>,
+    pending: Mutex<Vec<Record>>,
+}
+
+impl SqliteExport {
+    /// Creates the database with empty tables, replacing one left by an
+    /// earlier run so it only holds the packages of this run.
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(SqliteExport {
+            connection: Mutex::new(connection),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn insert(&self, package_info: &PackageInfo) -> anyhow::Result<()> {
+        let record = Record {
+            attribute: package_info.attribute.clone(),
+            name: package_info.name.clone(),
+            version: package_info.version.clone(),
+            description: package_info.description.clone(),
+            homepage: package_info.homepage.clone(),
+            available: package_info.available,
+            broken: package_info.broken,
+            broken_reason: package_info.broken_reason.clone(),
+            priority: package_info.priority,
+            position: package_info.position.clone(),
+            drv_path: package_info.drv_path.clone(),
+            license: package_info.license_short_name.clone(),
+            maintainers: package_info
+                .maintainers
+                .iter()
+                .map(|m| MaintainerRow {
+                    handle: m.handle(),
+                    name: m.name.clone(),
+                    email: m.email.clone(),
+                    github: m.github.clone(),
+                })
+                .collect(),
+            outputs: package_info
+                .outputs
+                .iter()
+                .map(|output| {
+                    (
+                        output.clone(),
+                        package_info.output_paths.get(output).cloned(),
+                        package_info.outputs_to_install.contains(output),
+                    )
+                })
+                .collect(),
+            platforms: package_info.platforms.clone(),
+            dependencies: package_info.dependencies.clone(),
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.push(record);
+        if pending.len() >= BATCH_SIZE {
+            let batch = std::mem::take(&mut *pending);
+            // Holding the lock keeps a single writer at a time
+            self.commit(batch)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, batch: Vec<Record>) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut license_id = transaction.prepare_cached(
+                "INSERT INTO licenses (short_name) VALUES (?1)
+                 ON CONFLICT (short_name) DO UPDATE SET short_name = short_name
+                 RETURNING id",
+            )?;
+            let mut maintainer_id = transaction.prepare_cached(
+                "INSERT INTO maintainers (handle, name, email, github) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (handle) DO UPDATE SET handle = handle
+                 RETURNING id",
+            )?;
+            let mut package = transaction.prepare_cached(
+                "INSERT OR IGNORE INTO packages (attribute, name, version, description,
+                    homepage, available, broken, broken_reason, priority, position,
+                    drv_path, license_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                 RETURNING id",
+            )?;
+            let mut package_maintainer = transaction
+                .prepare_cached("INSERT OR IGNORE INTO package_maintainers VALUES (?1, ?2)")?;
+            let mut output = transaction
+                .prepare_cached("INSERT OR IGNORE INTO outputs VALUES (?1, ?2, ?3, ?4)")?;
+            let mut platform =
+                transaction.prepare_cached("INSERT OR IGNORE INTO platforms VALUES (?1, ?2)")?;
+            let mut dependency =
+                transaction.prepare_cached("INSERT OR IGNORE INTO dependencies VALUES (?1, ?2)")?;
+
+            for record in &batch {
+                let license: i64 =
+                    license_id.query_row(params![record.license], |row| row.get(0))?;
+                // An attribute written before keeps its first row
+                let Some(package_id): Option<i64> = package
+                    .query_row(
+                        params![
+                            record.attribute,
+                            record.name,
+                            record.version,
+                            record.description,
+                            record.homepage,
+                            record.available,
+                            record.broken,
+                            record.broken_reason,
+                            record.priority,
+                            record.position,
+                            record.drv_path,
+                            license,
+                        ],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                else {
+                    continue;
+                };
+                for maintainer in &record.maintainers {
+                    let id: i64 = maintainer_id.query_row(
+                        params![
+                            maintainer.handle,
+                            maintainer.name,
+                            maintainer.email,
+                            maintainer.github,
+                        ],
+                        |row| row.get(0),
+                    )?;
+                    package_maintainer.execute(params![package_id, id])?;
+                }
+                for (name, path, installed) in &record.outputs {
+                    output.execute(params![package_id, name, path, installed])?;
+                }
+                for name in &record.platforms {
+                    platform.execute(params![package_id, name])?;
+                }
+                for drv_path in &record.dependencies {
+                    dependency.execute(params![package_id, drv_path])?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Commits whatever is still queued.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.commit(batch)
+    }
+}
@@ -0,0 +1,58 @@
+use crate::Args;
+use anyhow::Context;
+use colored::*;
+use serde_json::Value;
+use std::fs;
+
+/// Prints the counts of the last run in `--outdir` from its summary.json,
+/// and why each failed package failed from errors.json.
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    let path = format!("{}/summary.json", args.outdir);
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}, generate a vault first", path))?;
+    let summary: Value =
+        serde_json::from_str(&data).with_context(|| format!("failed to parse {}", path))?;
+
+    println!(
+        "{} {} ({})",
+        "📊 Last run:".cyan().bold(),
+        summary["generated_at"]
+            .as_str()
+            .unwrap_or("unknown")
+            .bright_white(),
+        summary["revision"]
+            .as_str()
+            .unwrap_or("unknown")
+            .bright_white()
+    );
+    for (label, key) in [
+        ("Packages in packages.json", "total"),
+        ("Matching filters", "matched"),
+        ("Processed", "processed"),
+        ("Errors", "errors"),
+        ("Skipped as known failing", "known_failing"),
+        ("Resumed from an earlier run", "resumed"),
+        ("Failed post-note hooks", "hook_failures"),
+    ] {
+        if let Some(count) = summary[key].as_u64() {
+            println!("   {}: {}", label, count.to_string().bright_white());
+        }
+    }
+
+    // Without errors.json there are just no details to show
+    let errors: Vec<Value> = fs::read_to_string(format!("{}/errors.json", args.outdir))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+    if !errors.is_empty() {
+        println!("{}", "❌ Failed packages:".red().bold());
+        for error in &errors {
+            println!(
+                "   {} ({})",
+                error["package"].as_str().unwrap_or_default().red(),
+                error["error"].as_str().unwrap_or_default().bright_black()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::PackageInfo;
+use colored::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::Command;
+
+/// Quotes `value` as a Typst string literal so it can be embedded in markup
+/// with `#"..."` without any of its characters being interpreted.
+fn quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("#\"{}\"", escaped)
+}
+
+/// Renders a single package as one page of the catalog.
+pub fn generate_package_page(package_info: &PackageInfo) -> String {
+    let mut content = String::new();
+
+    content.push_str("#pagebreak(weak: true)\n");
+    content.push_str(&format!("= {}\n\n", quote(&package_info.name)));
+
+    if let Some(ref description) = package_info.description {
+        content.push_str(&format!("{}\n\n", quote(description)));
+    }
+
+    content.push_str("#table(\n  columns: (auto, 1fr),\n  stroke: none,\n");
+    let mut row = |label: &str, value: &str| {
+        content.push_str(&format!("  [*{}*], [{}],\n", label, quote(value)));
+    };
+    row("Version", &package_info.version);
+    row("License", &package_info.license_short_name);
+    row(
+        "Available",
+        if package_info.available { "yes" } else { "no" },
+    );
+    row(
+        "Broken",
+        match (package_info.broken, &package_info.broken_reason) {
+            (true, Some(reason)) => reason,
+            (true, None) => "yes",
+            (false, _) => "no",
+        },
+    );
+    if let Some(priority) = package_info.priority {
+        row("Priority", &priority.to_string());
+    }
+    if let Some(ref homepage) = package_info.homepage {
+        row("Homepage", homepage);
+    }
+    if let Some(ref download_page) = package_info.download_page {
+        row("Download page", download_page);
+    }
+    for url in package_info.repositories.values() {
+        row("Repository", url);
+    }
+    if !package_info.platforms.is_empty() {
+        row("Platforms", &package_info.platforms.join(", "));
+    }
+    if !package_info.bad_platforms.is_empty() {
+        row("Broken on", &package_info.bad_platforms.join(", "));
+    }
+    row("Derivation", &package_info.drv_path);
+    for source in &package_info.sources {
+        match source.urls.first().or(source.store_path.as_ref()) {
+            Some(location) => row("Source", location),
+            None => continue,
+        }
+        if let Some(ref hash) = source.hash {
+            row("Source hash", hash);
+        }
+    }
+    for field in &package_info.extracted {
+        match &field.value {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(s) => row(&field.label, s),
+            other => row(&field.label, &other.to_string()),
+        }
+    }
+    content.push_str(")\n\n");
+
+    if let Some(ref long_desc) = package_info.long_description {
+        content.push_str("== Description\n\n");
+        content.push_str(&format!("{}\n\n", quote(long_desc)));
+    }
+
+    if !package_info.maintainers.is_empty() {
+        content.push_str("== Maintainers\n\n");
+        for maintainer in &package_info.maintainers {
+            content.push_str(&format!("- {}\n", quote(&maintainer.display())));
+        }
+        content.push('\n');
+    }
+
+    if !package_info.teams.is_empty() {
+        content.push_str("== Teams\n\n");
+        for team in &package_info.teams {
+            content.push_str(&format!("- {}\n", quote(&team.short_name)));
+        }
+        content.push('\n');
+    }
+
+    content
+}
+
+pub fn catalog_path(outdir: &str) -> String {
+    format!("{}/catalog.typ", outdir)
+}
+
+/// Creates `{outdir}/catalog.typ` and writes the document preamble. Package
+/// pages are appended in order by the caller.
+pub fn create_catalog(outdir: &str) -> std::io::Result<BufWriter<File>> {
+    let mut writer = BufWriter::new(File::create(catalog_path(outdir))?);
+    writer.write_all(b"#set document(title: \"Nixpkgs package catalog\")\n")?;
+    writer.write_all(b"#set page(paper: \"a4\", numbering: \"1\")\n")?;
+    writer.write_all(b"#set text(size: 10pt)\n\n")?;
+    Ok(writer)
+}
+
+/// Renders `catalog.pdf` next to the finished catalog if `typst` is
+/// installed, leaving the Typst source in place either way.
+pub fn render_pdf(outdir: &str) {
+    let typ_path = catalog_path(outdir);
+    println!(
+        "{} {}",
+        "✅ Typst catalog written to:".green().bold(),
+        typ_path.bright_white()
+    );
+
+    let pdf_path = format!("{}/catalog.pdf", outdir);
+    match Command::new("typst")
+        .args(["compile", &typ_path, &pdf_path])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            println!(
+                "{} {}",
+                "✅ PDF catalog rendered to:".green().bold(),
+                pdf_path.bright_white()
+            );
+        }
+        Ok(output) => {
+            eprintln!(
+                "{} {}",
+                "⚠️  typst failed to render the PDF:".yellow().bold(),
+                String::from_utf8_lossy(&output.stderr).trim().red()
+            );
+        }
+        Err(_) => {
+            println!(
+                "{}",
+                "ℹ️  typst not found in PATH, skipping PDF rendering".yellow()
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+
+/// Digits of an ID, as many as a minute-resolution Zettelkasten timestamp.
+const DIGITS: u32 = 12;
+
+pub fn path(outdir: &str) -> String {
+    format!("{}/zettel-ids.json", outdir)
+}
+
+/// IDs given to attribute paths for `--naming zettel`, kept in
+/// `zettel-ids.json` so a package keeps its ID, and the links to its note
+/// keep working, even when another package's ID took the one its hash
+/// picks.
+#[derive(Default)]
+pub struct ZettelIds {
+    ids: BTreeMap<String, String>,
+}
+
+/// FNV-1a, which unlike the standard library's hasher is the same on every
+/// build.
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl ZettelIds {
+    /// Reads the IDs of `outdir`, none if it has no index or an unreadable
+    /// one.
+    pub fn load(outdir: &str) -> Self {
+        let ids = fs::read_to_string(path(outdir))
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        ZettelIds { ids }
+    }
+
+    /// Gives every attribute without an ID one derived from its hash, or
+    /// the next free one if another attribute has that already. Attributes
+    /// are taken in sorted order, so the same packages get the same IDs.
+    pub fn assign<'a>(&mut self, attributes: impl IntoIterator<Item = &'a str>) {
+        let modulus = 10u64.pow(DIGITS);
+        let mut taken: HashSet<String> = self.ids.values().cloned().collect();
+        let mut missing: Vec<&str> = attributes
+            .into_iter()
+            .filter(|attribute| !self.ids.contains_key(*attribute))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        for attribute in missing {
+            let mut number = fnv1a(attribute) % modulus;
+            let id = loop {
+                let id = format!("{:0width$}", number, width = DIGITS as usize);
+                if taken.insert(id.clone()) {
+                    break id;
+                }
+                number = (number + 1) % modulus;
+            };
+            self.ids.insert(attribute.to_string(), id);
+        }
+    }
+
+    pub fn get(&self, attribute: &str) -> Option<&str> {
+        self.ids.get(attribute).map(String::as_str)
+    }
+
+    pub fn save(&self, outdir: &str) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(&self.ids)?;
+        crate::write_if_changed(&path(outdir), &format!("{}\n", data)).map(|_| ())
+    }
+}
@@ -0,0 +1,395 @@
+//! Runs the whole generation against fixtures/packages.json, with `nix` and
+//! `nix-instantiate` replaced by shell scripts.
+#![cfg(unix)]
+
+use nixpkgs_vault::{parse_args, run, RunSummary};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Once;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/packages.json");
+
+/// Echoes the git url as the fetched nixpkgs, so every test brings its own.
+const NIX_INSTANTIATE: &str = r#"#!/bin/sh
+for arg; do expr=$arg; done
+url=${expr#*url = \"}
+printf '"%s"\n' "${url%%\"*}"
+"#;
+
+/// Answers `nix derivation show` from the `names` directory of the fake
+/// nixpkgs, which maps attributes to derivation names, and logs each call to
+/// its `calls` file. With a `fail-batches` file, calls for more than one
+/// attribute fail; a file in `fail-once` fails the next call for that
+/// attribute.
+const NIX: &str = r#"#!/bin/sh
+count=0
+for arg; do
+  case $arg in *#*) nixpkgs=${arg%%#*}; count=$((count + 1));; esac
+done
+echo "$*" >> "$nixpkgs/calls"
+if [ -e "$nixpkgs/fail-batches" ] && [ "$count" -gt 1 ]; then
+  echo "error: batch failed" >&2
+  exit 1
+fi
+output=
+for arg; do
+  case $arg in *#*) ;; *) continue;; esac
+  attribute=${arg#*#}
+  if [ -e "$nixpkgs/fail-once/$attribute" ]; then
+    rm "$nixpkgs/fail-once/$attribute"
+    echo "error: cannot connect to socket" >&2
+    exit 1
+  fi
+  if [ ! -f "$nixpkgs/names/$attribute" ]; then
+    echo "error: flake does not provide attribute '$attribute'" >&2
+    exit 1
+  fi
+  name=$(cat "$nixpkgs/names/$attribute")
+  drv="/nix/store/00000000000000000000000000000000-$name.drv"
+  output="$output${output:+,}\"$drv\":{\"name\":\"$name\",\"outputs\":{\"out\":{\"path\":\"/nix/store/00000000000000000000000000000000-$name\"}},\"inputDrvs\":{},\"inputSrcs\":[],\"env\":{}}"
+done
+printf '{%s}\n' "$output"
+"#;
+
+/// Puts the stubs first on PATH, once for all tests of the process.
+fn stub_nix() {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        let bin = std::env::temp_dir().join(format!("nixpkgs-vault-stubs-{}", std::process::id()));
+        fs::create_dir_all(&bin).unwrap();
+        for (name, script) in [("nix", NIX), ("nix-instantiate", NIX_INSTANTIATE)] {
+            let path = bin.join(name);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path = std::env::var("PATH").unwrap_or_default();
+        std::env::set_var("PATH", format!("{}:{}", bin.display(), path));
+    });
+}
+
+/// A fake nixpkgs checkout knowing every package of the fixture, and an
+/// output directory holding the fixture as its packages.json.
+struct Vault {
+    root: String,
+}
+
+impl Vault {
+    fn new(test: &str) -> Self {
+        stub_nix();
+        let root = std::env::temp_dir()
+            .join(format!("nixpkgs-vault-{}-{}", test, std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = fs::remove_dir_all(&root);
+        let vault = Vault { root };
+
+        for file in [
+            "default.nix",
+            "pkgs/top-level/all-packages.nix",
+            "pkgs/top-level/packages-config.nix",
+        ] {
+            let path = Path::new(&vault.nixpkgs()).join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{ }\n").unwrap();
+        }
+        let packages_json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(FIXTURE).unwrap()).unwrap();
+        let names = format!("{}/names", vault.nixpkgs());
+        fs::create_dir_all(&names).unwrap();
+        for (attribute, info) in packages_json["packages"].as_object().unwrap() {
+            let name = info["name"].as_str().unwrap();
+            fs::write(format!("{}/{}", names, attribute), name).unwrap();
+        }
+
+        fs::create_dir_all(vault.outdir()).unwrap();
+        fs::copy(FIXTURE, format!("{}/packages.json", vault.outdir())).unwrap();
+        vault
+    }
+
+    fn nixpkgs(&self) -> String {
+        format!("{}/nixpkgs", self.root)
+    }
+
+    fn outdir(&self) -> String {
+        format!("{}/vault", self.root)
+    }
+
+    /// Generates the vault with `extra` arguments.
+    fn run(&self, extra: &[&str]) -> RunSummary {
+        let (nixpkgs, outdir) = (self.nixpkgs(), self.outdir());
+        let mut argv = vec![
+            "nixpkgs-vault",
+            "--git-url",
+            &nixpkgs,
+            "--outdir",
+            &outdir,
+            "--layout",
+            "dendron",
+            "--force",
+            "--quiet",
+        ];
+        argv.extend(extra);
+        run(parse_args(argv).unwrap()).unwrap().unwrap()
+    }
+
+    /// The `nix` invocations so far.
+    fn nix_calls(&self) -> Vec<String> {
+        fs::read_to_string(format!("{}/calls", self.nixpkgs()))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn note(&self, stem: &str) -> String {
+        fs::read_to_string(format!("{}/packages/{}.md", self.outdir(), stem)).unwrap()
+    }
+}
+
+impl Drop for Vault {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn every_package_gets_a_note() {
+    let vault = Vault::new("generate");
+    let summary = vault.run(&[]);
+
+    assert_eq!(summary.total(), 12);
+    assert_eq!(summary.processed(), 12);
+    assert!(summary.failed_packages().is_empty());
+    assert!(vault
+        .nix_calls()
+        .iter()
+        .all(|call| call.starts_with("derivation show --impure ")));
+    let note = vault.note("hello");
+    assert!(note.contains("# hello\n"));
+    assert!(note.contains("  - \"license/gpl3Plus\"\n"));
+    assert!(note.contains("hello-2.12.1.drv"));
+    assert!(vault
+        .note("python3Packages.requests")
+        .contains("requests-2.32.3.drv"));
+    let index = fs::read_to_string(format!("{}/index.md", vault.outdir())).unwrap();
+    assert!(index.contains("- **Total**: 12\n"));
+}
+
+#[test]
+fn missing_attributes_are_reported_as_failures() {
+    let vault = Vault::new("missing");
+    fs::remove_file(format!("{}/names/jq", vault.nixpkgs())).unwrap();
+    let summary = vault.run(&[]);
+
+    assert_eq!(summary.processed(), 12);
+    assert_eq!(summary.failed_packages(), ["jq"]);
+    assert!(vault.note("hello").contains("# hello\n"));
+}
+
+#[test]
+fn resume_evaluates_only_packages_without_a_note() {
+    let vault = Vault::new("resume");
+    let summary = vault.run(&["--no-cache", "--batch-size", "1", "--limit", "5"]);
+    assert_eq!(summary.processed(), 5);
+    let first_run = vault.nix_calls();
+    assert_eq!(first_run.len(), 5);
+
+    // --limit stands in for an interrupted run
+    let summary = vault.run(&["--no-cache", "--batch-size", "1", "--resume"]);
+    assert_eq!(summary.processed(), 7);
+    let second_run = &vault.nix_calls()[first_run.len()..];
+    assert_eq!(second_run.len(), 7);
+    assert!(second_run.iter().all(|call| !first_run.contains(call)));
+    let index = fs::read_to_string(format!("{}/index.md", vault.outdir())).unwrap();
+    assert!(index.contains("- **Total**: 12\n"));
+}
+
+#[test]
+fn failed_batches_fall_back_to_one_call_per_package() {
+    let vault = Vault::new("batch-fallback");
+    fs::write(format!("{}/fail-batches", vault.nixpkgs()), "").unwrap();
+    let summary = vault.run(&["--batch-size", "4"]);
+
+    assert_eq!(summary.processed(), 12);
+    assert!(summary.failed_packages().is_empty());
+    let calls = vault.nix_calls();
+    let batches = calls.iter().filter(|call| call.matches('#').count() > 1);
+    assert_eq!(batches.count(), 3);
+    assert_eq!(calls.len(), 3 + 12);
+    assert!(vault.note("hello").contains("hello-2.12.1.drv"));
+}
+
+#[test]
+fn filters_combine() {
+    let vault = Vault::new("filters");
+    let packages_json_path = format!("{}/packages.json", vault.outdir());
+    let mut packages_json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&packages_json_path).unwrap()).unwrap();
+    let packages = &mut packages_json["packages"];
+    packages["ripgrep"]["meta"]["broken"] = true.into();
+    packages["nodePackages.prettier"]["meta"]["platforms"] = serde_json::json!(["x86_64-linux"]);
+    fs::write(&packages_json_path, packages_json.to_string()).unwrap();
+
+    let summary = vault.run(&[
+        "--filter-license",
+        "mit",
+        "--filter-license",
+        "bsd3",
+        "--exclude-broken",
+        "--filter-platform",
+        "aarch64-darwin",
+    ]);
+    assert_eq!(summary.total(), 12);
+    assert_eq!(summary.processed(), 3);
+    let evaluated = vault.nix_calls().concat();
+    for attribute in ["#jq", "#python3Packages.numpy", "#haskellPackages.aeson"] {
+        assert!(evaluated.contains(attribute));
+    }
+    assert!(!evaluated.contains("#ripgrep"));
+    assert!(!evaluated.contains("#nodePackages.prettier"));
+
+    // Licenses match regardless of case
+    let summary = vault.run(&["--filter-license", "MIT"]);
+    assert_eq!(summary.processed(), 3);
+}
+
+#[test]
+fn failed_evaluations_are_retried_unless_the_attribute_is_missing() {
+    let vault = Vault::new("retries");
+    let fail_once = format!("{}/fail-once", vault.nixpkgs());
+    fs::create_dir_all(&fail_once).unwrap();
+    fs::write(format!("{}/hello", fail_once), "").unwrap();
+    fs::write(format!("{}/git", fail_once), "").unwrap();
+    fs::remove_file(format!("{}/names/jq", vault.nixpkgs())).unwrap();
+    let summary = vault.run(&["--batch-size", "1", "--retries", "2", "--include", "hello"]);
+    assert!(summary.failed_packages().is_empty());
+    let summary = vault.run(&["--batch-size", "1", "--retries", "2", "--include", "jq"]);
+    assert_eq!(summary.failed_packages(), ["jq"]);
+    let summary = vault.run(&["--batch-size", "1", "--retries", "0", "--include", "git"]);
+    assert_eq!(summary.failed_packages(), ["git"]);
+
+    let calls = vault.nix_calls();
+    let calls_for = |attribute: &str| {
+        let installable = format!("#{}", attribute);
+        calls
+            .iter()
+            .filter(|call| call.ends_with(&installable))
+            .count()
+    };
+    assert_eq!(calls_for("hello"), 2);
+    assert_eq!(calls_for("jq"), 1);
+    assert_eq!(calls_for("git"), 1);
+}
+
+#[test]
+fn ndjson_has_a_header_then_one_record_per_package() {
+    let vault = Vault::new("ndjson");
+    let path = format!("{}/packages.ndjson", vault.root);
+    vault.run(&["--json-out", &path]);
+
+    let lines: Vec<serde_json::Value> = fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let (header, records) = lines.split_first().unwrap();
+    assert_eq!(header["type"], "header");
+    assert_eq!(header["version"], 2);
+    assert_eq!(header["count"], 12);
+    assert_eq!(header["git_url"], vault.nixpkgs().as_str());
+    assert_eq!(records.len(), 12);
+
+    // Every field the README documents
+    let fields = [
+        "name",
+        "attribute",
+        "version",
+        "available",
+        "broken",
+        "broken_reason",
+        "description",
+        "long_description",
+        "long_description_truncated",
+        "homepage",
+        "download_page",
+        "repositories",
+        "license_short_name",
+        "platforms",
+        "bad_platforms",
+        "priority",
+        "position",
+        "maintainers",
+        "teams",
+        "drv_path",
+        "drv_missing",
+        "outputs",
+        "outputs_to_install",
+        "output_paths",
+        "output_sizes",
+        "input_srcs",
+        "input_drvs",
+        "dependencies",
+        "extracted",
+        "sources",
+    ];
+    for record in records {
+        assert_eq!(record["type"], "package");
+        for field in fields {
+            assert!(
+                record.get(field).is_some(),
+                "{} has no {}",
+                record["name"],
+                field
+            );
+        }
+    }
+    let hello = records
+        .iter()
+        .find(|record| record["attribute"] == "hello")
+        .unwrap();
+    assert_eq!(hello["license_short_name"], "gpl3Plus");
+    assert_eq!(hello["maintainers"][0]["github"], "stv0g");
+    assert!(hello["drv_path"]
+        .as_str()
+        .unwrap()
+        .ends_with("-hello-2.12.1.drv"));
+}
+
+#[test]
+fn refresh_rejects_hugo_notes() {
+    let vault = Vault::new("refresh-hugo");
+    vault.run(&["--frontmatter-style", "hugo"]);
+    let note = vault.note("hello");
+    assert!(note.starts_with("+++\n"));
+    assert!(note.contains("\ntitle = \"hello\"\n"));
+
+    let (nixpkgs, outdir) = (vault.nixpkgs(), vault.outdir());
+    for extra in [&[][..], &["--frontmatter-style", "hugo"][..]] {
+        let mut argv = vec![
+            "nixpkgs-vault",
+            "--git-url",
+            &nixpkgs,
+            "--outdir",
+            &outdir,
+            "--force",
+            "--refresh-metadata",
+        ];
+        argv.extend(extra);
+        let error = run(parse_args(argv).unwrap()).err().unwrap().to_string();
+        assert!(error.contains("Jekyll"), "{}", error);
+    }
+    assert_eq!(vault.note("hello"), note);
+}
+
+#[test]
+fn skip_and_limit_select_a_range_of_the_sorted_names() {
+    let vault = Vault::new("skip");
+    let summary = vault.run(&["--batch-size", "1", "--skip", "1", "--limit", "1"]);
+
+    // coreutils, curl, git, ...
+    assert_eq!(summary.processed(), 1);
+    let calls = vault.nix_calls();
+    assert_eq!(calls.len(), 1);
+    assert!(calls[0].ends_with("#curl"));
+}